@@ -1,195 +1,883 @@
-use std::{collections::VecDeque, sync::mpsc};
+use std::{
+  collections::VecDeque,
+  error::Error,
+  fmt, io,
+  path::PathBuf,
+  str::FromStr,
+  time::{Duration, Instant},
+};
 
+pub use analysis_thread::AnalysisThread;
 use audioviz::spectrum::{
-  Frequency, config::ProcessorConfig as SpectrumProcessorConfig,
-  processor::Processor as SpectrumProcessor,
+  config::ProcessorConfig as SpectrumProcessorConfig, processor::Processor as SpectrumProcessor,
 };
-use cpal::traits::{DeviceTrait, HostTrait};
+pub use band_energy::{BandEnergies, BandEnergyConfig, BandRange, NamedBand};
+pub use capture::CaptureSource;
+pub use chroma::{Harmony, Mode};
+use chroma::{KeyDetector, PITCH_CLASS_COUNT, PitchClassMap};
+use cqt::ConstantQ;
+use descriptors::DescriptorExtractor;
+pub use descriptors::SpectralDescriptors;
+pub use device::{DeviceConfig, DeviceSelector, list_devices};
+pub use file::{FileConfig, FileSource};
+use gain::AutoGain;
+pub use gain::GainConfig;
+pub use generator::{GeneratorConfig, SignalGenerator, StereoMode};
+use layout::BandMap;
+pub use layout::LayoutConfig;
+use loudness::LoudnessMeter;
+pub use loudness::{Loudness, LoudnessLevels};
+use onset::OnsetDetector;
+pub use onset::{Beat, OnsetConfig};
+pub use pcm::{PcmConfig, PcmSource};
+pub use silence::SilenceConfig;
+use silence::SilenceDetector;
+pub use smoothing::SmoothingConfig;
+use smoothing::SpectrumSmoother;
+pub use snapshot::SnapshotInterpolator;
+pub use source::{AudioSource, PlaybackCommand, SourceStatus};
+pub use stereo::{PAN_BAND_COUNT, StereoImage};
+pub use tempo::Tempo;
+use tempo::TempoTracker;
+pub use window::WindowFunction;
+
+mod analysis_thread;
+mod band_energy;
+mod capture;
+mod chroma;
+mod cqt;
+mod decoder;
+mod descriptors;
+mod device;
+mod file;
+mod gain;
+mod generator;
+mod layout;
+mod loudness;
+mod onset;
+mod pcm;
+mod ring_buffer;
+mod silence;
+mod smoothing;
+mod snapshot;
+mod source;
+mod stereo;
+mod tempo;
+mod window;
+
+pub const MIN_FFT_SIZE: usize = 256;
+pub const MAX_FFT_SIZE: usize = 32768;
+const MIN_HOP_SIZE: usize = 64;
+
+/// How the source channels are passed on to the renderer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChannelMode {
+  /// Every channel averaged into one
+  #[default]
+  Mixed,
+  /// Every channel kept separate
+  Split,
+}
+
+impl FromStr for ChannelMode {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "mixed" => Ok(Self::Mixed),
+      "split" => Ok(Self::Split),
+      _ => Err(format!(
+        "unknown channel mode `{}`, expected mixed or split",
+        value
+      )),
+    }
+  }
+}
+
+/// Transform that turns the sample blocks into a spectrum.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpectrumBackend {
+  /// audioviz's FFT, with linearly spaced bins
+  #[default]
+  Fft,
+  /// Constant-Q transform, with the same number of bins in every octave
+  ConstantQ,
+}
+
+impl FromStr for SpectrumBackend {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "fft" => Ok(Self::Fft),
+      "cqt" => Ok(Self::ConstantQ),
+      _ => Err(format!(
+        "unknown spectrum backend `{}`, expected fft or cqt",
+        value
+      )),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnalysisConfig {
+  pub channel_mode: ChannelMode,
+  pub backend: SpectrumBackend,
+  /// Resolution of the constant-Q backend
+  pub bins_per_octave: usize,
+  pub window: WindowFunction,
+  /// Samples per channel in every FFT block, a power of two
+  pub fft_size: usize,
+  /// New samples needed before the next block is analyzed
+  pub hop_size: usize,
+  pub layout: LayoutConfig,
+  pub smoothing: SmoothingConfig,
+  pub onset: OnsetConfig,
+  pub gain: GainConfig,
+  pub bands: BandEnergyConfig,
+  pub silence: SilenceConfig,
+}
+
+impl Default for AnalysisConfig {
+  fn default() -> Self {
+    Self {
+      channel_mode: ChannelMode::default(),
+      backend: SpectrumBackend::default(),
+      bins_per_octave: 24,
+      window: WindowFunction::default(),
+      fft_size: 4096,
+      hop_size: 512,
+      layout: LayoutConfig::default(),
+      smoothing: SmoothingConfig::default(),
+      onset: OnsetConfig::default(),
+      gain: GainConfig::default(),
+      bands: BandEnergyConfig::default(),
+      silence: SilenceConfig::default(),
+    }
+  }
+}
+
+/// The analysis results of one processed frame.
+///
+/// Holds a single channel in [`ChannelMode::Mixed`] and one per source channel in
+/// [`ChannelMode::Split`], every channel with the same length.
+#[derive(Clone, Default)]
+pub struct AudioFrame {
+  /// Smoothed over time
+  pub spectrum: Vec<Vec<f32>>,
+  /// Recent maximum of every spectrum bin
+  pub peaks: Vec<Vec<f32>>,
+  pub waveform: Vec<Vec<f32>>,
+  /// Measured before the channels are mixed, so it is available in every channel mode
+  pub stereo: StereoImage,
+  pub beat: Beat,
+  pub tempo: Tempo,
+  pub loudness: Loudness,
+  pub harmony: Harmony,
+  pub descriptors: SpectralDescriptors,
+  /// Smoothed like the spectrum, of all channels together
+  pub band_energies: BandEnergies,
+  /// Smoothed like `band_energies`, one per source channel in every channel mode
+  pub channel_band_energies: Vec<BandEnergies>,
+}
+
 pub struct AudioProcessor {
-  input_stream: cpal::Stream,
-  data_rx: mpsc::Receiver<Vec<f32>>,
+  source: Box<dyn AudioSource>,
+  read_buffer: Vec<f32>,
   channel_buffers: Vec<VecDeque<f32>>,
+  /// Coefficients of `config.window` for blocks of `config.fft_resolution` samples
+  window: Vec<f32>,
+  /// Interleaved frames read but not analyzed yet, less than one FFT block of them
+  pending_samples: VecDeque<f32>,
+  /// Built on first use, since it depends on the FFT size, sampling rate and resolution
+  band_map: Option<BandMap>,
+  /// Built on first use like `band_map`, only with the constant-Q backend
+  constant_q: Option<ConstantQ>,
+  /// Center frequencies of the bins of the current backend, set together with `band_map`
+  bin_frequencies: Vec<f32>,
+  /// Built together with `band_map`
+  pitch_class_map: Option<PitchClassMap>,
+  smoother: SpectrumSmoother,
+  onset_detector: OnsetDetector,
+  tempo_tracker: TempoTracker,
+  /// Measures every frame read, not only the analyzed blocks
+  loudness_meter: LoudnessMeter,
+  key_detector: KeyDetector,
+  descriptor_extractor: DescriptorExtractor,
+  band_energy_config: BandEnergyConfig,
+  band_energy_smoother: SpectrumSmoother,
+  auto_gain: AutoGain,
+  /// Checks every hop, and every call without one so silence is noticed without frames
+  silence_detector: SilenceDetector,
+  /// Time of the newest analyzed hop. It advances by one hop duration per hop, so hops
+  /// that are read in a burst keep their spacing, and only follows the wall clock while
+  /// no frames arrive
+  clock: Instant,
   config: AudioProcessorConfig,
 }
 
 impl AudioProcessor {
-  pub fn init() -> Self {
-    let (data_tx, data_rx) = mpsc::channel();
-
-    let device = find_output_monitor().unwrap();
-
-    let stream_config = device.default_input_config().unwrap().into();
-    let input_stream = device
-      .build_input_stream(
-        &stream_config,
-        move |data, _: &_| {
-          let _ = data_tx.send(data.to_vec());
-        },
-        Self::handle_stream_error,
-        None,
-      )
-      .unwrap();
-
-    let channel_count = stream_config.channels as usize;
+  pub fn new(source: Box<dyn AudioSource>, analysis_config: &AnalysisConfig) -> Self {
+    let channel_count = source.channel_count();
+    let fft_resolution = analysis_config
+      .fft_size
+      .next_power_of_two()
+      .clamp(MIN_FFT_SIZE, MAX_FFT_SIZE);
     let config = AudioProcessorConfig {
       resolution: None,
-      fft_resolution: 1024 * 3,
-      sampling_rate: stream_config.sample_rate.0,
+      fft_resolution,
+      hop_size: analysis_config
+        .hop_size
+        .clamp(MIN_HOP_SIZE.min(fft_resolution), fft_resolution),
+      window: analysis_config.window,
+      sampling_rate: source.sample_rate(),
       channel_count,
+      channel_mode: analysis_config.channel_mode,
+      backend: analysis_config.backend,
+      bins_per_octave: analysis_config.bins_per_octave.max(1),
+      layout: analysis_config.layout.clone(),
     };
 
     Self {
-      input_stream,
-      data_rx,
+      source,
+      read_buffer: Vec::new(),
       channel_buffers: vec![VecDeque::with_capacity(config.fft_resolution); channel_count],
+      window: config.window.build(config.fft_resolution),
+      pending_samples: VecDeque::new(),
+      band_map: None,
+      constant_q: None,
+      bin_frequencies: Vec::new(),
+      pitch_class_map: None,
+      smoother: SpectrumSmoother::new(analysis_config.smoothing.clone()),
+      onset_detector: OnsetDetector::new(analysis_config.onset.clone()),
+      tempo_tracker: TempoTracker::new(),
+      loudness_meter: LoudnessMeter::new(config.sampling_rate, channel_count),
+      key_detector: KeyDetector::new(),
+      descriptor_extractor: DescriptorExtractor::new(),
+      band_energy_config: analysis_config.bands.clone(),
+      band_energy_smoother: SpectrumSmoother::new(analysis_config.smoothing.clone()),
+      auto_gain: AutoGain::new(analysis_config.gain.clone()),
+      silence_detector: SilenceDetector::new(analysis_config.silence.clone()),
+      clock: Instant::now(),
       config,
     }
   }
 
-  pub fn resolution(&self) -> Option<usize> {
-    self.config.resolution
+  pub fn status(&self) -> SourceStatus {
+    self.source.status()
+  }
+
+  pub fn control(&mut self, command: PlaybackCommand) {
+    self.source.control(command);
   }
 
   pub fn set_resolution(&mut self, new_resolution: Option<usize>) {
-    self.config.resolution = new_resolution;
+    if new_resolution != self.config.resolution {
+      self.config.resolution = new_resolution;
+      self.band_map = None;
+    }
+  }
+
+  pub fn fft_size(&self) -> usize {
+    self.config.fft_resolution
+  }
+
+  pub fn hop_size(&self) -> usize {
+    self.config.hop_size
+  }
+
+  /// Time of the hop analyzed last on the sample clock, which advances by one hop duration
+  /// per hop, so frames analyzed in one burst are still spaced apart.
+  pub fn frame_time(&self) -> Instant {
+    self.clock
+  }
+
+  /// Time between analyzed blocks at the sampling rate of the source.
+  pub fn hop_duration(&self) -> Duration {
+    Duration::from_secs_f64(self.config.hop_size as f64 / self.config.sampling_rate.max(1) as f64)
   }
 
-  fn process_frequencies(&mut self) -> Vec<Vec<Frequency>> {
+  pub fn cycle_window_function(&mut self) {
+    self.config.window = self.config.window.next();
+    self.window = self.config.window.build(self.config.fft_resolution);
+    log::info!("window function: {:?}", self.config.window);
+  }
+
+  /// Changes the FFT size, rounded up to a power of two.
+  ///
+  /// The newest samples are kept and a grown buffer is padded with silence, so the
+  /// analysis continues without waiting for the buffers to fill up again.
+  pub fn set_fft_size(&mut self, fft_size: usize) {
+    let fft_size = fft_size
+      .next_power_of_two()
+      .clamp(MIN_FFT_SIZE, MAX_FFT_SIZE);
+    if fft_size == self.config.fft_resolution {
+      return;
+    }
+
+    self.config.fft_resolution = fft_size;
+    self.config.hop_size = self.config.hop_size.min(fft_size);
+    self.window = self.config.window.build(fft_size);
+    self.band_map = None;
+    self.constant_q = None;
+    self.build_constant_q();
+
+    let block_len = self.block_len();
+    for buffer in &mut self.channel_buffers {
+      let excess_elements = buffer.len().saturating_sub(block_len);
+      buffer.drain(0..excess_elements);
+      while buffer.len() < block_len {
+        buffer.push_front(0.0);
+      }
+    }
+    log::info!(
+      "FFT size: {}, hop size: {}",
+      self.config.fft_resolution,
+      self.config.hop_size
+    );
+  }
+
+  pub fn set_hop_size(&mut self, hop_size: usize) {
+    self.config.hop_size = hop_size.clamp(
+      MIN_HOP_SIZE.min(self.config.fft_resolution),
+      self.config.fft_resolution,
+    );
+    log::info!("hop size: {}", self.config.hop_size);
+  }
+
+  pub fn toggle_auto_gain(&mut self) {
+    let enabled = !self.auto_gain.is_enabled();
+    self.auto_gain.set_enabled(enabled);
+    log::info!(
+      "automatic gain control: {}",
+      if enabled { "on" } else { "bypassed" }
+    );
+  }
+
+  /// Whether the input has been silent for longer than the configured hold time.
+  pub fn is_idle(&self) -> bool {
+    self.silence_detector.is_idle()
+  }
+
+  /// Fades from 1 while audio plays to 0 once idle, and back when the audio returns.
+  pub fn activity(&self) -> f32 {
+    self.silence_detector.activity()
+  }
+
+  /// Builds the constant-Q transform if it is the backend and not built yet, since it
+  /// depends on the FFT size and sampling rate.
+  fn build_constant_q(&mut self) {
+    if self.config.backend == SpectrumBackend::ConstantQ && self.constant_q.is_none() {
+      self.constant_q = Some(ConstantQ::new(
+        self.config.sampling_rate,
+        self.config.fft_resolution,
+        self.config.bins_per_octave,
+        self.config.layout.min_frequency,
+        self.config.layout.max_frequency,
+      ));
+    }
+  }
+
+  /// Samples per channel kept for the analysis, the constant-Q transform may need longer
+  /// blocks than the FFT.
+  fn block_len(&self) -> usize {
+    self
+      .constant_q
+      .as_ref()
+      .map_or(self.config.fft_resolution, |constant_q| {
+        constant_q.block_size().max(self.config.fft_resolution)
+      })
+  }
+
+  /// Frequencies of the FFT bins, from DC to the Nyquist frequency.
+  fn fft_bin_frequencies(&self) -> Vec<f32> {
+    let bin_width = self.config.sampling_rate as f32 / self.config.fft_resolution as f32;
+    (0..=self.config.fft_resolution / 2)
+      .map(|bin| bin as f32 * bin_width)
+      .collect()
+  }
+
+  /// Computes the spectrum of every channel, distributed over the bands of the layout,
+  /// the magnitudes of the bins averaged over the channels and the band energies of every
+  /// channel.
+  fn process_frequencies(&mut self) -> (Vec<Vec<f32>>, Vec<f32>, Vec<BandEnergies>) {
     if self
       .channel_buffers
       .iter()
-      .any(|channel_buffer| channel_buffer.len() < self.config.fft_resolution)
+      .any(|channel_buffer| channel_buffer.len() < self.block_len())
     {
-      return Vec::new();
+      return (Vec::new(), Vec::new(), Vec::new());
+    }
+
+    if self.band_map.is_none() {
+      let bin_frequencies = match &self.constant_q {
+        Some(constant_q) => constant_q.frequencies().to_vec(),
+        None => self.fft_bin_frequencies(),
+      };
+      let band_count = self
+        .config
+        .layout
+        .band_count
+        .or(self.config.resolution)
+        .unwrap_or(bin_frequencies.len());
+      self.band_map = Some(BandMap::new(
+        &self.config.layout,
+        &bin_frequencies,
+        band_count,
+      ));
+      self.pitch_class_map = Some(PitchClassMap::new(&bin_frequencies));
+      self.bin_frequencies = bin_frequencies;
     }
+    let band_map = self.band_map.as_ref().unwrap();
 
     let mut channel_spectrum_buffers = Vec::with_capacity(self.config.channel_count);
+    let mut channel_band_energies = Vec::with_capacity(self.config.channel_count);
+    let mut mixed_magnitudes = vec![0.0; self.bin_frequencies.len()];
+    let channel_weight = 1.0 / self.config.channel_count as f32;
+    let mut mix = |magnitudes: &[f32]| {
+      for (mixed, magnitude) in mixed_magnitudes.iter_mut().zip(magnitudes) {
+        *mixed += magnitude * channel_weight;
+      }
+    };
 
-    for channel_buffer in self.channel_buffers.iter_mut() {
+    for channel_buffer in self.channel_buffers.iter() {
       let buffer_start_offset = channel_buffer.len() - self.config.fft_resolution;
+
+      // Both backends produce bins that are distributed over the same bands,
+      // so the renderer can't tell them apart
+      if let Some(constant_q) = &mut self.constant_q {
+        let block_start = channel_buffer.len() - constant_q.block_size();
+        let magnitudes = constant_q.transform(channel_buffer.range(block_start..).copied());
+        mix(&magnitudes);
+        channel_spectrum_buffers.push(band_map.apply(&magnitudes));
+        channel_band_energies.push(
+          self
+            .band_energy_config
+            .measure(&magnitudes, &self.bin_frequencies),
+        );
+        continue;
+      }
+
       let mut audio_data = SpectrumProcessor::from_raw_data(
-        self.config.into_spectrum_processor_config(),
+        self.config.to_spectrum_processor_config(),
         channel_buffer
           .range(buffer_start_offset..)
-          .copied()
+          .zip(&self.window)
+          .map(|(sample, window)| sample * window)
           .collect(),
       );
 
-      audio_data.compute_all();
-      channel_spectrum_buffers.push(audio_data.freq_buffer);
+      // Only the FFT and its volume normalisation are taken from audioviz, the window and
+      // the distribution over the bands are handled here
+      audio_data.fft();
+      audio_data.normalize_frequency_volume();
+      mix(&audio_data.raw_buffer);
+      channel_spectrum_buffers.push(band_map.apply(&audio_data.raw_buffer));
+      channel_band_energies.push(
+        self
+          .band_energy_config
+          .measure(&audio_data.raw_buffer, &self.bin_frequencies),
+      );
     }
 
-    channel_spectrum_buffers
+    (
+      channel_spectrum_buffers,
+      mixed_magnitudes,
+      channel_band_energies,
+    )
   }
 
-  pub fn process_data(&mut self) -> Option<Vec<f32>> {
-    let Ok(data) = self.data_rx.try_recv() else {
-      return None;
-    };
+  /// Starts over with empty buffers when the source changed its format, like after
+  /// reconnecting to another device.
+  fn sync_source_format(&mut self) {
+    let sampling_rate = self.source.sample_rate();
+    let channel_count = self.source.channel_count();
+
+    if sampling_rate == self.config.sampling_rate && channel_count == self.config.channel_count {
+      return;
+    }
+
+    self.config.sampling_rate = sampling_rate;
+    self.config.channel_count = channel_count;
+    self.band_map = None;
+    self.constant_q = None;
+    self.channel_buffers = vec![VecDeque::with_capacity(self.config.fft_resolution); channel_count];
+    self.pending_samples.clear();
+    self.loudness_meter = LoudnessMeter::new(sampling_rate, channel_count);
+  }
+
+  /// Reads the new frames of the source and analyzes the next block once a full hop of
+  /// them is available.
+  ///
+  /// Every block advances by exactly one hop, so when more than one hop is pending, calling
+  /// this again analyzes the next one without waiting for new frames.
+  pub fn process_data(&mut self) -> Option<AudioFrame> {
+    self.sync_source_format();
+    self.build_constant_q();
+    let block_len = self.block_len();
+
+    self.read_buffer.clear();
+    let frame_count = self.source.read_frames(&mut self.read_buffer, block_len);
+    if frame_count > 0 {
+      self.loudness_meter.process(&self.read_buffer);
+      self.pending_samples.extend(&self.read_buffer);
+
+      // Frames older than a block would never make it into one
+      let channel_count = self.config.channel_count;
+      let excess_samples = self
+        .pending_samples
+        .len()
+        .saturating_sub(block_len * channel_count);
+      self.pending_samples.drain(0..excess_samples);
+    }
 
-    for channels in data.chunks_exact(self.config.channel_count) {
-      for (channel_number, sample) in channels.iter().enumerate() {
-        self.channel_buffers[channel_number].push_back(*sample);
+    // Blocks overlap by the FFT size minus the hop size
+    let hop_samples = self.config.hop_size * self.config.channel_count;
+    if self.pending_samples.len() < hop_samples {
+      // Frames always lag behind a little, only a source that stopped delivering them for
+      // longer than a block moves the clock on
+      let now = Instant::now();
+      let block_duration =
+        Duration::from_secs_f64(block_len as f64 / self.config.sampling_rate.max(1) as f64);
+      if now.saturating_duration_since(self.clock) > block_duration {
+        self.clock = now;
       }
+      self.silence_detector.process(&[], self.clock);
+      return None;
+    }
+
+    self.clock += self.hop_duration();
+    let hop: Vec<f32> = self.pending_samples.drain(0..hop_samples).collect();
+    self.silence_detector.process(&hop, self.clock);
+    for (index, sample) in hop.into_iter().enumerate() {
+      self.channel_buffers[index % self.config.channel_count].push_back(sample);
     }
 
     for buffer in &mut self.channel_buffers {
-      let excess_elements = buffer.len().saturating_sub(self.config.fft_resolution);
+      let excess_elements = buffer.len().saturating_sub(block_len);
       buffer.drain(0..excess_elements);
     }
 
-    let frequencies = self.process_frequencies();
-    if frequencies.len() == 0 {
+    let (spectrum, magnitudes, channel_band_energies) = self.process_frequencies();
+    if spectrum.is_empty() {
       return None;
     }
 
-    let resolution = frequencies[0].len();
-    let mut spectrum = Vec::with_capacity(resolution);
-
-    for index in 0..resolution {
-      let mut average_amplitude = 0.0;
-      for channel in &frequencies {
-        average_amplitude += channel[index].volume;
-      }
+    let stereo = StereoImage::analyze(&self.channel_buffers, &spectrum);
+    let now = self.clock;
+    let beat = self.onset_detector.process(&spectrum, now);
+    let tempo = self
+      .tempo_tracker
+      .process(self.onset_detector.flux(), &beat, now);
 
-      average_amplitude /= frequencies.len() as f32;
+    let mut chroma = [0.0; PITCH_CLASS_COUNT];
+    if let Some(pitch_class_map) = &self.pitch_class_map {
+      pitch_class_map.fold(&magnitudes, &mut chroma);
+    }
+    let harmony = self.key_detector.process(&chroma, now);
+    let descriptors = self.descriptor_extractor.process(
+      &magnitudes,
+      &self.bin_frequencies,
+      &self.channel_buffers,
+      self.onset_detector.flux(),
+      now,
+    );
+    let loudness = self.loudness_meter.loudness();
+    let gain = self.auto_gain.process(loudness.program.short_term, now);
+
+    // Gain is applied before smoothing, so the held peaks stay in the same range
+    let mut spectrum = self.mix_channels(spectrum);
+    apply_gain(&mut spectrum, gain);
+    let peaks = self.smoother.apply(&mut spectrum, now);
+
+    // Smoothed as a spectrum with one bin per band, the first channel holds the bands of
+    // all channels together and the others those of every source channel
+    let mixed_band_energies = self
+      .band_energy_config
+      .measure(&magnitudes, &self.bin_frequencies);
+    let mut band_energies: Vec<Vec<f32>> = [mixed_band_energies]
+      .iter()
+      .chain(&channel_band_energies)
+      .map(|energies| energies.0.to_vec())
+      .collect();
+    apply_gain(&mut band_energies, gain);
+    self.band_energy_smoother.apply(&mut band_energies, now);
+    let mut band_energies = band_energies
+      .iter()
+      .map(|energies| BandEnergies(std::array::from_fn(|band| energies[band])));
+    let mixed_band_energies = band_energies.next().unwrap_or_default();
+    let channel_band_energies = band_energies.collect();
+
+    let mut waveform = self.mix_channels(self.get_waveforms());
+    apply_gain(&mut waveform, gain);
+
+    Some(AudioFrame {
+      spectrum,
+      peaks,
+      waveform,
+      stereo,
+      beat,
+      tempo,
+      loudness,
+      harmony,
+      descriptors,
+      band_energies: mixed_band_energies,
+      channel_band_energies,
+    })
+  }
 
-      spectrum.push(average_amplitude);
+  /// Averages `channels` into one unless they should be kept separate.
+  fn mix_channels(&self, channels: Vec<Vec<f32>>) -> Vec<Vec<f32>> {
+    if self.config.channel_mode == ChannelMode::Split || channels.len() <= 1 {
+      return channels;
     }
 
-    Some(spectrum)
+    let len = channels
+      .iter()
+      .map(|channel| channel.len())
+      .min()
+      .unwrap_or(0);
+    let mixed = (0..len)
+      .map(|index| {
+        channels.iter().map(|channel| channel[index]).sum::<f32>() / channels.len() as f32
+      })
+      .collect();
+
+    vec![mixed]
   }
 
-  pub fn get_waveform(&self) -> Vec<f32> {
+  fn get_waveforms(&self) -> Vec<Vec<f32>> {
     let Some(waveform_len) = self.channel_buffers.iter().map(|buffer| buffer.len()).min() else {
       return Vec::new();
     };
 
     let waveform_buffer_len = self.config.resolution.unwrap_or(waveform_len);
-    let mut waveform_buffer = Vec::with_capacity(waveform_buffer_len);
+    let num_samples = waveform_len.min(waveform_buffer_len);
 
-    let num_samples = if let Some(resolution) = self.config.resolution {
-      waveform_len.min(resolution)
-    } else {
-      waveform_len
-    };
-
-    for index in (waveform_len - num_samples)..waveform_len {
-      let mut average_sample = 0.0;
-      for buffer in &self.channel_buffers {
-        average_sample += buffer[index];
-      }
-
-      average_sample /= self.channel_buffers.len() as f32;
-      waveform_buffer.push(average_sample);
-    }
-
-    waveform_buffer.resize(waveform_buffer_len, 0.0);
-    waveform_buffer
+    self
+      .channel_buffers
+      .iter()
+      .map(|buffer| {
+        let mut waveform_buffer = Vec::with_capacity(waveform_buffer_len);
+        waveform_buffer.extend(buffer.range(buffer.len() - num_samples..));
+        waveform_buffer.resize(waveform_buffer_len, 0.0);
+        waveform_buffer
+      })
+      .collect()
   }
+}
 
-  fn handle_stream_error(error: cpal::StreamError) {
-    eprintln!("an error occurred on the audio stream: {}", error);
+fn apply_gain(channels: &mut [Vec<f32>], gain: f32) {
+  for value in channels.iter_mut().flatten() {
+    *value *= gain;
   }
 }
 
 struct AudioProcessorConfig {
   fft_resolution: usize,
+  hop_size: usize,
+  window: WindowFunction,
   resolution: Option<usize>,
   sampling_rate: u32,
   channel_count: usize,
+  channel_mode: ChannelMode,
+  backend: SpectrumBackend,
+  bins_per_octave: usize,
+  layout: LayoutConfig,
 }
 
 impl AudioProcessorConfig {
-  fn into_spectrum_processor_config(&self) -> SpectrumProcessorConfig {
+  fn to_spectrum_processor_config(&self) -> SpectrumProcessorConfig {
     SpectrumProcessorConfig {
       sampling_rate: self.sampling_rate,
-      resolution: self.resolution,
       volume: 1.0,
       ..SpectrumProcessorConfig::default()
     }
   }
 }
 
-fn find_output_monitor() -> Option<cpal::Device> {
-  let host = cpal::default_host();
-  let mut devices = host
-    .output_devices()
-    .into_iter()
-    .flatten()
-    .chain(host.output_devices().into_iter().flatten());
+#[derive(Debug)]
+pub enum AudioError {
+  UnknownHost(String),
+  HostUnavailable(cpal::HostUnavailable),
+  Devices(cpal::DevicesError),
+  NoInputDevice,
+  DeviceNotFound(DeviceSelector),
+  NoInputSupport(String),
+  DefaultConfig(cpal::DefaultStreamConfigError),
+  SupportedConfigs(cpal::SupportedStreamConfigsError),
+  UnsupportedFormat(String),
+  BuildStream(cpal::BuildStreamError),
+  PlayStream(cpal::PlayStreamError),
+  NoOutputDevice,
+  EmptyPlaylist,
+  OpenFile(PathBuf, io::Error),
+  DecodeFile(PathBuf, symphonia::core::errors::Error),
+  UnsupportedFile(PathBuf),
+}
 
-  return devices.find(|device| device.supports_input());
+impl fmt::Display for AudioError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Self::UnknownHost(name) => write!(f, "no audio host named `{}` is available", name),
+      Self::HostUnavailable(error) => write!(f, "audio host is unavailable: {}", error),
+      Self::Devices(error) => write!(f, "failed to enumerate audio devices: {}", error),
+      Self::NoInputDevice => write!(f, "no audio device to capture from was found"),
+      Self::DeviceNotFound(DeviceSelector::Index(index)) => {
+        write!(f, "no audio device with index {}", index)
+      }
+      Self::DeviceNotFound(DeviceSelector::Name(name)) => {
+        write!(f, "no audio device matching `{}`", name)
+      }
+      Self::NoInputSupport(name) => write!(f, "audio device `{}` can not be captured", name),
+      Self::DefaultConfig(error) => {
+        write!(f, "failed to query the default stream config: {}", error)
+      }
+      Self::SupportedConfigs(error) => {
+        write!(f, "failed to query the supported input configs: {}", error)
+      }
+      Self::UnsupportedFormat(name) => {
+        write!(f, "audio device `{}` has no supported sample format", name)
+      }
+      Self::BuildStream(error) => write!(f, "failed to open the audio stream: {}", error),
+      Self::PlayStream(error) => write!(f, "failed to start the audio stream: {}", error),
+      Self::NoOutputDevice => write!(f, "no audio output device was found"),
+      Self::EmptyPlaylist => write!(f, "the playlist does not contain any files"),
+      Self::OpenFile(path, error) => write!(f, "failed to read {}: {}", path.display(), error),
+      Self::DecodeFile(path, error) => {
+        write!(f, "failed to decode {}: {}", path.display(), error)
+      }
+      Self::UnsupportedFile(path) => write!(
+        f,
+        "can not play {}: unknown format, only WAV, FLAC, Ogg Vorbis and MP3 can be decoded",
+        path.display()
+      ),
+    }
+  }
 }
 
-fn is_device_supported(device: &cpal::Device) -> bool {
-  if !device.supports_input() {
-    return false;
+impl Error for AudioError {}
+
+#[cfg(test)]
+mod tests {
+  use std::f32::consts::TAU;
+
+  use super::*;
+
+  const SAMPLE_RATE: u32 = 48000;
+
+  /// Plays interleaved samples from memory, a hop at a time like a capture stream that is
+  /// read as soon as every hop arrives.
+  struct BufferSource {
+    channel_count: usize,
+    samples: VecDeque<f32>,
+    frames_per_read: usize,
+  }
+
+  impl AudioSource for BufferSource {
+    fn sample_rate(&self) -> u32 {
+      SAMPLE_RATE
+    }
+
+    fn channel_count(&self) -> usize {
+      self.channel_count
+    }
+
+    fn read_frames(&mut self, buffer: &mut Vec<f32>, max_frames: usize) -> usize {
+      let frame_count = (self.samples.len() / self.channel_count)
+        .min(self.frames_per_read)
+        .min(max_frames);
+      buffer.extend(self.samples.drain(0..frame_count * self.channel_count));
+      frame_count
+    }
+  }
+
+  /// Analyzes every hop of `channels`, which all have the same length.
+  fn analyze(channels: &[Vec<f32>], analysis_config: &AnalysisConfig) -> Vec<AudioFrame> {
+    let source = BufferSource {
+      channel_count: channels.len(),
+      samples: (0..channels[0].len())
+        .flat_map(|index| channels.iter().map(move |channel| channel[index]))
+        .collect(),
+      frames_per_read: analysis_config.hop_size,
+    };
+    let mut processor = AudioProcessor::new(Box::new(source), analysis_config);
+
+    let mut frames = Vec::new();
+    for _ in 0..channels[0].len() / analysis_config.hop_size {
+      frames.extend(processor.process_data());
+    }
+    frames
   }
 
-  let Ok(mut configs) = device.supported_input_configs() else {
-    return false;
-  };
+  fn sine(frequency: f32, seconds: f32) -> Vec<f32> {
+    (0..(seconds * SAMPLE_RATE as f32) as usize)
+      .map(|index| 0.5 * (TAU * frequency * index as f32 / SAMPLE_RATE as f32).sin())
+      .collect()
+  }
+
+  #[test]
+  fn spectrum_peaks_in_the_band_of_a_sine() {
+    // 240 bands of 100 Hz up to the Nyquist frequency
+    let analysis_config = AnalysisConfig {
+      layout: LayoutConfig {
+        min_frequency: 0.0,
+        max_frequency: 24000.0,
+        band_count: Some(240),
+        ..Default::default()
+      },
+      ..Default::default()
+    };
+    let frames = analyze(&[sine(1050.0, 1.0)], &analysis_config);
 
-  configs.any(|config| matches!(config.sample_format(), cpal::SampleFormat::F32))
+    let spectrum = &frames.last().unwrap().spectrum[0];
+    let loudest = (0..spectrum.len())
+      .max_by(|a, b| spectrum[*a].total_cmp(&spectrum[*b]))
+      .unwrap();
+    assert_eq!(loudest, 10);
+  }
+
+  #[test]
+  fn identical_channels_correlate() {
+    let channel = sine(440.0, 0.5);
+    let frames = analyze(&[channel.clone(), channel], &AnalysisConfig::default());
+
+    let stereo = frames.last().unwrap().stereo;
+    assert!(
+      stereo.correlation > 0.99,
+      "correlation {}",
+      stereo.correlation
+    );
+    assert!(stereo.side < 0.001, "side {}", stereo.side);
+  }
+
+  #[test]
+  fn band_energies_are_measured_per_channel() {
+    let bass = sine(100.0, 0.5);
+    let silence = vec![0.0; bass.len()];
+    let frames = analyze(&[bass, silence], &AnalysisConfig::default());
+
+    let frame = frames.last().unwrap();
+    let [left, right] = &frame.channel_band_energies[..] else {
+      panic!("expected two channels");
+    };
+    assert!(left.get(NamedBand::Bass) > 0.01);
+    assert_eq!(right.get(NamedBand::Bass), 0.0);
+    // The mix averages both channels
+    let mixed = frame.band_energies.get(NamedBand::Bass);
+    assert!((mixed - left.get(NamedBand::Bass) / 2.0).abs() < 0.01 * mixed);
+  }
+
+  #[test]
+  fn impulse_train_produces_beats() {
+    // Clicks every half second in silence
+    let mut samples = vec![0.0; 8 * SAMPLE_RATE as usize];
+    for click in samples.chunks_mut(SAMPLE_RATE as usize / 2) {
+      click[..32].fill(0.8);
+    }
+    let frames = analyze(&[samples], &AnalysisConfig::default());
+
+    let beats = frames.last().unwrap().beat.count;
+    assert!(beats >= 10, "{} beats", beats);
+  }
 }
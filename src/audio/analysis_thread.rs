@@ -0,0 +1,157 @@
+use std::{
+  sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicU32, Ordering},
+    mpsc,
+  },
+  thread::{self, JoinHandle},
+  time::Instant,
+};
+
+use super::{
+  AudioError, AudioProcessor, SourceStatus,
+  snapshot::{Snapshot, SnapshotReader, SnapshotWriter, snapshot_buffer},
+};
+
+type Command = Box<dyn FnOnce(&mut AudioProcessor) + Send>;
+
+/// State the analysis thread publishes besides the snapshots, updated even while no
+/// frames arrive.
+struct Shared {
+  connected: AtomicBool,
+  idle: AtomicBool,
+  /// Stored as its bit pattern
+  activity: AtomicU32,
+  stop: AtomicBool,
+}
+
+/// Runs an `AudioProcessor` on a thread of its own, analyzing every hop as it arrives
+/// independently of the frame rate.
+pub struct AnalysisThread {
+  shared: Arc<Shared>,
+  snapshots: SnapshotReader,
+  commands: mpsc::Sender<Command>,
+  handle: Option<JoinHandle<()>>,
+}
+
+impl AnalysisThread {
+  /// Starts the thread and builds the processor with `open` on it, since the streams of
+  /// some audio backends can't be moved between threads.
+  pub fn spawn(
+    open: impl FnOnce() -> Result<AudioProcessor, AudioError> + Send + 'static,
+  ) -> Result<Self, AudioError> {
+    let shared = Arc::new(Shared {
+      connected: AtomicBool::new(true),
+      idle: AtomicBool::new(false),
+      activity: AtomicU32::new(1f32.to_bits()),
+      stop: AtomicBool::new(false),
+    });
+    let (snapshot_writer, snapshot_reader) = snapshot_buffer();
+    let (command_tx, command_rx) = mpsc::channel();
+    let (opened_tx, opened_rx) = mpsc::sync_channel(1);
+
+    let thread_shared = shared.clone();
+    let handle = thread::Builder::new()
+      .name("analysis".to_string())
+      .spawn(move || {
+        let processor = match open() {
+          Ok(processor) => processor,
+          Err(error) => {
+            let _ = opened_tx.send(Err(error));
+            return;
+          }
+        };
+        let _ = opened_tx.send(Ok(()));
+        run(processor, snapshot_writer, &thread_shared, &command_rx);
+      })
+      .expect("the analysis thread should be spawned");
+
+    match opened_rx.recv() {
+      Ok(Ok(())) => Ok(Self {
+        shared,
+        snapshots: snapshot_reader,
+        commands: command_tx,
+        handle: Some(handle),
+      }),
+      Ok(Err(error)) => {
+        let _ = handle.join();
+        Err(error)
+      }
+      Err(_) => panic!("the analysis thread should report whether the source opened"),
+    }
+  }
+
+  /// Runs `command` on the processor before the next hop is analyzed.
+  pub fn send(&self, command: impl FnOnce(&mut AudioProcessor) + Send + 'static) {
+    // Only fails once the thread is gone, and then there is nothing left to control
+    let _ = self.commands.send(Box::new(command));
+  }
+
+  /// The newest snapshot, if one was analyzed since the last call.
+  pub fn take_snapshot(&mut self) -> Option<Snapshot> {
+    self.snapshots.take()
+  }
+
+  pub fn status(&self) -> SourceStatus {
+    if self.shared.connected.load(Ordering::Relaxed) {
+      SourceStatus::Connected
+    } else {
+      SourceStatus::Disconnected
+    }
+  }
+
+  /// See [`AudioProcessor::is_idle`].
+  pub fn is_idle(&self) -> bool {
+    self.shared.idle.load(Ordering::Relaxed)
+  }
+
+  /// See [`AudioProcessor::activity`].
+  pub fn activity(&self) -> f32 {
+    f32::from_bits(self.shared.activity.load(Ordering::Relaxed))
+  }
+}
+
+impl Drop for AnalysisThread {
+  fn drop(&mut self) {
+    self.shared.stop.store(true, Ordering::Relaxed);
+    if let Some(handle) = self.handle.take() {
+      let _ = handle.join();
+    }
+  }
+}
+
+fn run(
+  mut processor: AudioProcessor,
+  mut snapshots: SnapshotWriter,
+  shared: &Shared,
+  commands: &mpsc::Receiver<Command>,
+) {
+  let mut next_hop = Instant::now();
+  while !shared.stop.load(Ordering::Relaxed) {
+    for command in commands.try_iter() {
+      command(&mut processor);
+    }
+
+    // Catches up on every full hop that arrived since the last wake up
+    while let Some(frame) = processor.process_data() {
+      snapshots.publish(Snapshot {
+        time: processor.frame_time(),
+        frame,
+      });
+    }
+
+    shared.connected.store(
+      processor.status() == SourceStatus::Connected,
+      Ordering::Relaxed,
+    );
+    shared.idle.store(processor.is_idle(), Ordering::Relaxed);
+    shared
+      .activity
+      .store(processor.activity().to_bits(), Ordering::Relaxed);
+
+    // Waking once per hop picks up each one about as soon as the source delivers it,
+    // falling behind skips ahead instead of rushing to catch up
+    next_hop = (next_hop + processor.hop_duration()).max(Instant::now());
+    thread::sleep(next_hop.saturating_duration_since(Instant::now()));
+  }
+}
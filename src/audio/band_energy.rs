@@ -0,0 +1,149 @@
+use std::str::FromStr;
+
+pub const NAMED_BAND_COUNT: usize = 6;
+
+/// Frequency bands with their own energy, from the lowest to the highest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NamedBand {
+  Sub,
+  Bass,
+  LowMid,
+  Mid,
+  Presence,
+  Air,
+}
+
+impl NamedBand {
+  const ALL: [Self; NAMED_BAND_COUNT] = [
+    Self::Sub,
+    Self::Bass,
+    Self::LowMid,
+    Self::Mid,
+    Self::Presence,
+    Self::Air,
+  ];
+
+  fn name(self) -> &'static str {
+    match self {
+      Self::Sub => "sub",
+      Self::Bass => "bass",
+      Self::LowMid => "low-mid",
+      Self::Mid => "mid",
+      Self::Presence => "presence",
+      Self::Air => "air",
+    }
+  }
+}
+
+impl FromStr for NamedBand {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    Self::ALL
+      .into_iter()
+      .find(|band| band.name() == value)
+      .ok_or_else(|| {
+        format!(
+          "unknown band `{}`, expected sub, bass, low-mid, mid, presence or air",
+          value
+        )
+      })
+  }
+}
+
+/// New frequency range of a band, parsed from `<name>=<min hz>-<max hz>`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BandRange {
+  pub band: NamedBand,
+  pub min_frequency: f32,
+  pub max_frequency: f32,
+}
+
+impl FromStr for BandRange {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    let invalid = || {
+      format!(
+        "invalid band `{}`, expected <name>=<min hz>-<max hz>",
+        value
+      )
+    };
+
+    let (band, range) = value.split_once('=').ok_or_else(invalid)?;
+    let (min, max) = range.split_once('-').ok_or_else(invalid)?;
+    let min_frequency: f32 = min.parse().map_err(|_| invalid())?;
+    let max_frequency: f32 = max.parse().map_err(|_| invalid())?;
+    if !(min_frequency >= 0.0 && min_frequency < max_frequency && max_frequency.is_finite()) {
+      return Err(format!(
+        "band `{}` needs a lower frequency below its upper one",
+        value
+      ));
+    }
+
+    Ok(Self {
+      band: band.parse()?,
+      min_frequency,
+      max_frequency,
+    })
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct BandEnergyConfig {
+  /// Lower and upper frequency of every band in Hz, in the order of [`NamedBand`]
+  pub ranges: [(f32, f32); NAMED_BAND_COUNT],
+}
+
+impl BandEnergyConfig {
+  pub fn set_range(&mut self, range: BandRange) {
+    self.ranges[range.band as usize] = (range.min_frequency, range.max_frequency);
+  }
+
+  /// RMS magnitude of the bins inside every band, `bin_frequencies` are the ascending
+  /// center frequencies of the bins in `magnitudes`.
+  pub fn measure(&self, magnitudes: &[f32], bin_frequencies: &[f32]) -> BandEnergies {
+    BandEnergies(self.ranges.map(|(min, max)| {
+      let start = bin_frequencies.partition_point(|frequency| *frequency < min);
+      let end = bin_frequencies.partition_point(|frequency| *frequency < max);
+      let bins = magnitudes
+        .get(start..end.min(magnitudes.len()))
+        .unwrap_or(&[]);
+      if bins.is_empty() {
+        return 0.0;
+      }
+
+      (bins
+        .iter()
+        .map(|magnitude| magnitude * magnitude)
+        .sum::<f32>()
+        / bins.len() as f32)
+        .sqrt()
+    }))
+  }
+}
+
+impl Default for BandEnergyConfig {
+  fn default() -> Self {
+    Self {
+      ranges: [
+        (20.0, 60.0),
+        (60.0, 250.0),
+        (250.0, 500.0),
+        (500.0, 2000.0),
+        (2000.0, 6000.0),
+        (6000.0, 20000.0),
+      ],
+    }
+  }
+}
+
+/// Energy of every named band, in the order of [`NamedBand`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BandEnergies(pub [f32; NAMED_BAND_COUNT]);
+
+impl BandEnergies {
+  pub fn get(&self, band: NamedBand) -> f32 {
+    self.0[band as usize]
+  }
+}
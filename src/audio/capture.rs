@@ -0,0 +1,238 @@
+use std::{
+  sync::mpsc,
+  time::{Duration, Instant},
+};
+
+use cpal::traits::StreamTrait;
+
+use super::{
+  AudioError, AudioSource, DeviceConfig, SourceStatus, device,
+  ring_buffer::{self, Consumer},
+};
+
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+/// How often to check whether the configured device is back while on a fallback device
+const PREFERRED_DEVICE_RETRY_DELAY: Duration = Duration::from_secs(5);
+/// Length of audio the ring buffer between the stream callback and the analyzer can hold
+const RING_BUFFER_SECONDS: usize = 1;
+/// A stream that delivers no data for this long is stalled. Sound servers also suspend
+/// idle monitor sources this way, so a stall alone never drops the stream
+const STALL_TIMEOUT: Duration = Duration::from_secs(2);
+/// How often a stalled stream checks whether its device is still listed, some backends
+/// never report an error when the device goes away
+const PRESENCE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+struct ActiveStream {
+  // Never read, but the stream stops capturing once it is dropped
+  _input_stream: cpal::Stream,
+  consumer: Consumer,
+  device_name: String,
+  last_data: Instant,
+  /// Set once the stall was logged, until data arrives again
+  is_stalled: bool,
+  last_presence_check: Option<Instant>,
+  /// Connected to a fallback instead of the configured device
+  is_fallback: bool,
+}
+
+/// Live capture from a cpal input device.
+///
+/// Stream errors are reported back through a channel and handled when frames are read.
+/// A lost device is reconnected with exponential backoff, falling back to the next
+/// suitable device while the configured one is unavailable.
+pub struct CaptureSource {
+  device_config: DeviceConfig,
+  stream: Option<ActiveStream>,
+  error_tx: mpsc::Sender<cpal::StreamError>,
+  error_rx: mpsc::Receiver<cpal::StreamError>,
+  sample_rate: u32,
+  channel_count: usize,
+  reconnect_delay: Duration,
+  next_reconnect: Instant,
+}
+
+impl CaptureSource {
+  pub fn open(device_config: &DeviceConfig) -> Result<Self, AudioError> {
+    let (error_tx, error_rx) = mpsc::channel();
+
+    let mut source = Self {
+      device_config: device_config.clone(),
+      stream: None,
+      error_tx,
+      error_rx,
+      sample_rate: 0,
+      channel_count: 0,
+      reconnect_delay: INITIAL_RECONNECT_DELAY,
+      next_reconnect: Instant::now(),
+    };
+
+    let device = device::find_input_device(device_config)?;
+    source.connect(&device, false)?;
+
+    Ok(source)
+  }
+
+  fn connect(&mut self, device: &cpal::Device, is_fallback: bool) -> Result<(), AudioError> {
+    let supported_config = device::negotiate_input_config(device)?;
+    let (producer, consumer) = ring_buffer::ring_buffer(
+      supported_config.sample_rate().0 as usize
+        * supported_config.channels() as usize
+        * RING_BUFFER_SECONDS,
+    );
+    let input_stream =
+      device::build_input_stream(device, &supported_config, producer, self.error_tx.clone())?;
+    input_stream.play().map_err(AudioError::PlayStream)?;
+
+    let device_name = device::device_name(device);
+    log::info!(
+      "capturing {} with {} samples",
+      device_name,
+      supported_config.sample_format()
+    );
+
+    // Errors of the previous stream are no longer relevant
+    self.error_rx.try_iter().for_each(drop);
+
+    self.stream = Some(ActiveStream {
+      _input_stream: input_stream,
+      consumer,
+      device_name,
+      last_data: Instant::now(),
+      is_stalled: false,
+      last_presence_check: None,
+      is_fallback,
+    });
+    self.sample_rate = supported_config.sample_rate().0;
+    self.channel_count = supported_config.channels() as usize;
+    self.reconnect_delay = INITIAL_RECONNECT_DELAY;
+    self.next_reconnect = Instant::now() + PREFERRED_DEVICE_RETRY_DELAY;
+
+    Ok(())
+  }
+
+  fn disconnect(&mut self, reason: &str) {
+    if self.stream.take().is_some() {
+      log::warn!("audio stream lost: {}", reason);
+      self.reconnect_delay = INITIAL_RECONNECT_DELAY;
+      self.next_reconnect = Instant::now() + self.reconnect_delay;
+    }
+  }
+
+  fn handle_errors(&mut self) {
+    while let Ok(error) = self.error_rx.try_recv() {
+      match error {
+        cpal::StreamError::DeviceNotAvailable => self.disconnect(&error.to_string()),
+        cpal::StreamError::BackendSpecific { .. } => {
+          log::warn!("an error occurred on the audio stream: {}", error)
+        }
+      }
+    }
+
+    self.check_stall();
+  }
+
+  /// Waits out a stalled stream as long as its device is still there.
+  fn check_stall(&mut self) {
+    let Some(stream) = &mut self.stream else {
+      return;
+    };
+    if stream.last_data.elapsed() <= STALL_TIMEOUT {
+      stream.is_stalled = false;
+      return;
+    }
+
+    if !stream.is_stalled {
+      stream.is_stalled = true;
+      log::info!(
+        "no audio data received from {}, waiting for it to resume",
+        stream.device_name
+      );
+    }
+
+    let check_due = stream
+      .last_presence_check
+      .is_none_or(|last_check| last_check.elapsed() >= PRESENCE_CHECK_INTERVAL);
+    if !check_due {
+      return;
+    }
+
+    stream.last_presence_check = Some(Instant::now());
+    if !device::is_device_present(self.device_config.host.as_deref(), &stream.device_name) {
+      self.disconnect("the device disappeared");
+    }
+  }
+
+  fn reconnect(&mut self) {
+    let now = Instant::now();
+    if now < self.next_reconnect {
+      return;
+    }
+
+    // Prefer the configured device, even when a fallback is already running
+    if let Ok(device) = device::find_input_device(&self.device_config) {
+      match self.connect(&device, false) {
+        Ok(()) => return,
+        Err(error) => log::debug!("failed to reconnect the audio device: {}", error),
+      }
+    }
+
+    if self.stream.is_some() {
+      self.next_reconnect = now + PREFERRED_DEVICE_RETRY_DELAY;
+      return;
+    }
+
+    // Without an explicit selection any working device is as good as the default one
+    let is_fallback = self.device_config.device.is_some();
+    for device in device::fallback_input_devices(self.device_config.host.as_deref()) {
+      match self.connect(&device, is_fallback) {
+        Ok(()) => return,
+        Err(error) => log::debug!("failed to connect a fallback audio device: {}", error),
+      }
+    }
+
+    self.reconnect_delay = (self.reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+    self.next_reconnect = now + self.reconnect_delay;
+  }
+}
+
+impl AudioSource for CaptureSource {
+  fn sample_rate(&self) -> u32 {
+    self.sample_rate
+  }
+
+  fn channel_count(&self) -> usize {
+    self.channel_count
+  }
+
+  fn read_frames(&mut self, buffer: &mut Vec<f32>, max_frames: usize) -> usize {
+    // Read before looking for a stall, so a long gap between reads is not mistaken for one
+    let mut frame_count = 0;
+    if let Some(stream) = &mut self.stream {
+      frame_count = stream
+        .consumer
+        .pop_latest(buffer, max_frames * self.channel_count)
+        / self.channel_count;
+
+      if frame_count > 0 {
+        stream.last_data = Instant::now();
+      }
+    }
+
+    self.handle_errors();
+
+    let needs_reconnect = self.stream.as_ref().is_none_or(|stream| stream.is_fallback);
+    if needs_reconnect {
+      self.reconnect();
+    }
+
+    frame_count
+  }
+
+  fn status(&self) -> SourceStatus {
+    match &self.stream {
+      Some(_) => SourceStatus::Connected,
+      None => SourceStatus::Disconnected,
+    }
+  }
+}
@@ -0,0 +1,184 @@
+use std::time::{Duration, Instant};
+
+use super::smoothing::smoothing_factor;
+
+pub const PITCH_CLASS_COUNT: usize = 12;
+/// Frequency ratio of a semitone
+const SEMITONE: f32 = 1.059_463_1;
+/// Bins above this are left out of the chromagram, harmonics blur the pitch classes there
+const MAX_FREQUENCY: f32 = 2100.0;
+/// Time constant of the chroma passed on, short enough to follow chord changes
+const CHROMA_SMOOTHING: Duration = Duration::from_millis(200);
+/// Time constant of the chroma the key is estimated from
+const KEY_SMOOTHING: Duration = Duration::from_secs(10);
+/// Krumhansl-Kessler key profiles, starting at the tonic
+const MAJOR_PROFILE: [f32; PITCH_CLASS_COUNT] = [
+  6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; PITCH_CLASS_COUNT] = [
+  6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+  #[default]
+  Major,
+  Minor,
+}
+
+/// Estimated musical key.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Key {
+  /// Pitch class of the tonic, 0 for C up to 11 for B
+  pub tonic: usize,
+  pub mode: Mode,
+  /// Correlation of the recent chroma with the profile of the key, from 0 to 1
+  pub confidence: f32,
+}
+
+/// Energy of every pitch class, and the key they suggest.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Harmony {
+  /// Smoothed chromagram from C to B, the strongest pitch class is 1
+  pub chroma: [f32; PITCH_CLASS_COUNT],
+  pub key: Key,
+}
+
+/// Precomputed pitch class of every analysis bin.
+pub struct PitchClassMap {
+  pitch_classes: Vec<Option<usize>>,
+}
+
+impl PitchClassMap {
+  /// `bin_frequencies` are the ascending center frequencies of the analysis bins.
+  ///
+  /// Bins spaced wider than a semitone can't tell neighbouring pitch classes apart and are
+  /// left out, which for a 4096 sample FFT at 48 kHz drops everything below about 200 Hz.
+  pub fn new(bin_frequencies: &[f32]) -> Self {
+    let pitch_classes = (0..bin_frequencies.len())
+      .map(|index| {
+        let frequency = bin_frequencies[index];
+        let resolves_semitones =
+          bin_spacing(bin_frequencies, index) <= frequency * (SEMITONE - 1.0);
+
+        (resolves_semitones && frequency <= MAX_FREQUENCY).then(|| {
+          // MIDI note number, 69 is A4 at 440 Hz
+          let note = (12.0 * (frequency / 440.0).log2() + 69.0).round() as i32;
+          note.rem_euclid(PITCH_CLASS_COUNT as i32) as usize
+        })
+      })
+      .collect();
+
+    Self { pitch_classes }
+  }
+
+  /// Adds the magnitude of every bin to its pitch class.
+  pub fn fold(&self, magnitudes: &[f32], chroma: &mut [f32; PITCH_CLASS_COUNT]) {
+    for (pitch_class, magnitude) in self.pitch_classes.iter().zip(magnitudes) {
+      if let Some(pitch_class) = pitch_class {
+        chroma[*pitch_class] += magnitude;
+      }
+    }
+  }
+}
+
+/// Mean distance from the bin at `index` to its neighbours.
+fn bin_spacing(bin_frequencies: &[f32], index: usize) -> f32 {
+  let below = index.saturating_sub(1);
+  let above = (index + 1).min(bin_frequencies.len() - 1);
+  if above == below {
+    return f32::INFINITY;
+  }
+
+  (bin_frequencies[above] - bin_frequencies[below]) / (above - below) as f32
+}
+
+/// Pearson correlation of two chroma vectors.
+fn correlation(a: &[f32; PITCH_CLASS_COUNT], b: impl Fn(usize) -> f32) -> f32 {
+  let mean_a = a.iter().sum::<f32>() / PITCH_CLASS_COUNT as f32;
+  let mean_b = (0..PITCH_CLASS_COUNT).map(&b).sum::<f32>() / PITCH_CLASS_COUNT as f32;
+
+  let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+  for (index, value_a) in a.iter().enumerate() {
+    let deviation_a = value_a - mean_a;
+    let deviation_b = b(index) - mean_b;
+    covariance += deviation_a * deviation_b;
+    variance_a += deviation_a * deviation_a;
+    variance_b += deviation_b * deviation_b;
+  }
+
+  if variance_a <= f32::EPSILON || variance_b <= f32::EPSILON {
+    return 0.0;
+  }
+  covariance / (variance_a * variance_b).sqrt()
+}
+
+/// Smooths the chromagram and estimates the key by matching the long-term chroma against
+/// the profile of every major and minor key.
+pub struct KeyDetector {
+  chroma: [f32; PITCH_CLASS_COUNT],
+  key_chroma: [f32; PITCH_CLASS_COUNT],
+  key: Key,
+  last_update: Option<Instant>,
+}
+
+impl KeyDetector {
+  pub fn new() -> Self {
+    Self {
+      chroma: [0.0; PITCH_CLASS_COUNT],
+      key_chroma: [0.0; PITCH_CLASS_COUNT],
+      key: Key::default(),
+      last_update: None,
+    }
+  }
+
+  pub fn process(&mut self, chroma: &[f32; PITCH_CLASS_COUNT], now: Instant) -> Harmony {
+    let elapsed = self
+      .last_update
+      .map_or(0.0, |last_update| (now - last_update).as_secs_f32());
+    self.last_update = Some(now);
+
+    let chroma_factor = smoothing_factor(elapsed, CHROMA_SMOOTHING);
+    let key_factor = smoothing_factor(elapsed, KEY_SMOOTHING);
+    for (index, value) in chroma.iter().enumerate() {
+      self.chroma[index] += (value - self.chroma[index]) * chroma_factor;
+      self.key_chroma[index] += (value - self.key_chroma[index]) * key_factor;
+    }
+
+    // Silence keeps the last key
+    if self.key_chroma.iter().any(|value| *value > f32::EPSILON) {
+      self.key = self.estimate_key();
+    }
+
+    let max = self.chroma.iter().copied().fold(0.0, f32::max);
+    Harmony {
+      chroma: self
+        .chroma
+        .map(|value| if max > f32::EPSILON { value / max } else { 0.0 }),
+      key: self.key,
+    }
+  }
+
+  fn estimate_key(&self) -> Key {
+    let mut best = Key::default();
+    let mut best_correlation = f32::MIN;
+
+    for tonic in 0..PITCH_CLASS_COUNT {
+      for (mode, profile) in [(Mode::Major, MAJOR_PROFILE), (Mode::Minor, MINOR_PROFILE)] {
+        let correlation = correlation(&self.key_chroma, |pitch_class| {
+          profile[(pitch_class + PITCH_CLASS_COUNT - tonic) % PITCH_CLASS_COUNT]
+        });
+        if correlation > best_correlation {
+          best_correlation = correlation;
+          best = Key {
+            tonic,
+            mode,
+            confidence: correlation.clamp(0.0, 1.0),
+          };
+        }
+      }
+    }
+
+    best
+  }
+}
@@ -0,0 +1,146 @@
+use std::{f32::consts::TAU, sync::Arc};
+
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+use super::MAX_FFT_SIZE;
+
+/// Spectral kernel weights below this fraction of a kernel's peak are dropped
+const SPARSITY_THRESHOLD: f32 = 0.005;
+
+/// Constant-Q transform computed from an FFT with precomputed sparse spectral kernels,
+/// after Brown and Puckette.
+///
+/// Every bin has the same ratio of frequency to bandwidth, so low notes are resolved as
+/// well as high ones. The transform runs on blocks of its own, long enough for the window
+/// of the lowest bin, so its size is independent of the FFT size. Blocks are at most
+/// [`MAX_FFT_SIZE`] samples, a minimum frequency whose window would not fit is raised.
+pub struct ConstantQ {
+  fft: Arc<dyn Fft<f32>>,
+  buffer: Vec<Complex<f32>>,
+  /// Scales the magnitudes to the level of the FFT backend
+  gain: f32,
+  frequencies: Vec<f32>,
+  /// Nonzero FFT bins and their conjugated kernel weights, for every constant-Q bin
+  kernels: Vec<Vec<(usize, Complex<f32>)>>,
+}
+
+impl ConstantQ {
+  /// `fft_size` is the size of the FFT backend, whose levels the transform matches.
+  pub fn new(
+    sample_rate: u32,
+    fft_size: usize,
+    bins_per_octave: usize,
+    min_frequency: f32,
+    max_frequency: f32,
+  ) -> Self {
+    let sample_rate = sample_rate as f32;
+    let max_frequency = max_frequency.min(sample_rate / 2.0);
+    let mut min_frequency = min_frequency.clamp(1.0, max_frequency);
+    let q = 1.0 / (2f32.powf(1.0 / bins_per_octave as f32) - 1.0);
+
+    // The lowest bin has the longest window
+    let block_size = ((q * sample_rate / min_frequency).ceil() as usize)
+      .next_power_of_two()
+      .min(MAX_FFT_SIZE);
+    let lowest_fitting_frequency = q * sample_rate / block_size as f32;
+    if min_frequency < lowest_fitting_frequency {
+      log::warn!(
+        "the constant-Q transform starts at {:.0} Hz instead of {:.0} Hz, the windows of \
+         lower bins would be longer than {} samples",
+        lowest_fitting_frequency,
+        min_frequency,
+        MAX_FFT_SIZE
+      );
+      min_frequency = lowest_fitting_frequency.min(max_frequency);
+    }
+
+    let octaves = (max_frequency / min_frequency).log2();
+    let bin_count = (octaves * bins_per_octave as f32).floor() as usize + 1;
+    let frequencies: Vec<f32> = (0..bin_count)
+      .map(|bin| min_frequency * 2f32.powf(bin as f32 / bins_per_octave as f32))
+      .collect();
+
+    let fft = FftPlanner::new().plan_fft_forward(block_size);
+    let kernels = frequencies
+      .iter()
+      .map(|frequency| {
+        let len = ((q * sample_rate / frequency).ceil() as usize).clamp(2, block_size);
+        spectral_kernel(fft.as_ref(), len, frequency / sample_rate)
+      })
+      .collect();
+
+    Self {
+      fft,
+      buffer: Vec::with_capacity(block_size),
+      // Matches a sine's level in the FFT backend, whose bins come out at
+      // amplitude * window gain * size / 2 and are scaled by 0.1 by audioviz
+      gain: 0.54 * fft_size as f32 * 0.1,
+      frequencies,
+      kernels,
+    }
+  }
+
+  /// Samples per channel every transform needs.
+  pub fn block_size(&self) -> usize {
+    self.fft.len()
+  }
+
+  /// Center frequencies of the bins, ascending.
+  pub fn frequencies(&self) -> &[f32] {
+    &self.frequencies
+  }
+
+  /// Computes the magnitude of every bin from the newest [`Self::block_size`] unwindowed
+  /// `samples`.
+  pub fn transform(&mut self, samples: impl IntoIterator<Item = f32>) -> Vec<f32> {
+    let block_size = self.block_size();
+    self.buffer.clear();
+    self
+      .buffer
+      .extend(samples.into_iter().map(|sample| Complex::new(sample, 0.0)));
+    self.buffer.resize(block_size, Complex::default());
+    self.fft.process(&mut self.buffer);
+
+    self
+      .kernels
+      .iter()
+      .map(|kernel| {
+        let sum: Complex<f32> = kernel
+          .iter()
+          .map(|(bin, weight)| self.buffer[*bin] * weight)
+          .sum();
+        sum.norm() / block_size as f32 * self.gain
+      })
+      .collect()
+  }
+}
+
+/// FFT of a Hann windowed complex sinusoid of `len` samples centered in the block,
+/// conjugated so applying it to a spectrum correlates the signal with the sinusoid.
+fn spectral_kernel(fft: &dyn Fft<f32>, len: usize, frequency: f32) -> Vec<(usize, Complex<f32>)> {
+  let fft_size = fft.len();
+  let start = (fft_size - len) / 2;
+  let window: Vec<f32> = (0..len)
+    .map(|index| 0.5 - 0.5 * (TAU * index as f32 / (len - 1) as f32).cos())
+    .collect();
+  let window_sum: f32 = window.iter().sum();
+
+  let mut kernel = vec![Complex::default(); fft_size];
+  for (index, weight) in window.iter().enumerate() {
+    let phase = TAU * frequency * index as f32;
+    kernel[start + index] = Complex::from_polar(weight / window_sum, phase);
+  }
+
+  fft.process(&mut kernel);
+  let peak = kernel
+    .iter()
+    .map(|weight| weight.norm())
+    .fold(0.0, f32::max);
+
+  kernel
+    .into_iter()
+    .enumerate()
+    .filter(|(_, weight)| weight.norm() >= peak * SPARSITY_THRESHOLD)
+    .map(|(bin, weight)| (bin, weight.conj()))
+    .collect()
+}
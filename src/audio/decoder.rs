@@ -0,0 +1,81 @@
+use std::io::{self, Cursor};
+
+use symphonia::core::{
+  audio::SampleBuffer, codecs::DecoderOptions, errors::Error, formats::FormatOptions,
+  io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+};
+
+/// Fully decoded audio with interleaved samples normalized to -1..1.
+pub struct DecodedAudio {
+  pub sample_rate: u32,
+  pub channel_count: usize,
+  pub samples: Vec<f32>,
+}
+
+/// Decodes a WAV, FLAC, Ogg Vorbis or MP3 file completely, `extension` helps to tell the
+/// container apart when the contents alone are ambiguous.
+pub fn decode(bytes: Vec<u8>, extension: Option<&str>) -> Result<DecodedAudio, Error> {
+  let stream = MediaSourceStream::new(Box::new(Cursor::new(bytes)), Default::default());
+  let mut hint = Hint::new();
+  if let Some(extension) = extension {
+    hint.with_extension(extension);
+  }
+
+  let mut format = symphonia::default::get_probe()
+    .format(
+      &hint,
+      stream,
+      &FormatOptions::default(),
+      &MetadataOptions::default(),
+    )?
+    .format;
+  let track = format
+    .default_track()
+    .ok_or(Error::DecodeError("no audio track"))?;
+  let track_id = track.id;
+  let mut sample_rate = track.codec_params.sample_rate;
+  let mut channel_count = track.codec_params.channels.map(|channels| channels.count());
+  let mut decoder =
+    symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+  let mut samples = Vec::new();
+  loop {
+    let packet = match format.next_packet() {
+      Ok(packet) => packet,
+      Err(Error::IoError(error)) if error.kind() == io::ErrorKind::UnexpectedEof => break,
+      Err(error) => return Err(error),
+    };
+    if packet.track_id() != track_id {
+      continue;
+    }
+
+    let decoded = match decoder.decode(&packet) {
+      Ok(decoded) => decoded,
+      // A corrupt packet only loses its own samples
+      Err(Error::DecodeError(error)) => {
+        log::warn!("skipping undecodable packet: {}", error);
+        continue;
+      }
+      Err(error) => return Err(error),
+    };
+
+    let spec = *decoded.spec();
+    sample_rate = Some(spec.rate);
+    channel_count = Some(spec.channels.count());
+
+    let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+    buffer.copy_interleaved_ref(decoded);
+    samples.extend_from_slice(buffer.samples());
+  }
+
+  match (sample_rate, channel_count) {
+    (Some(sample_rate), Some(channel_count)) if sample_rate > 0 && channel_count > 0 => {
+      Ok(DecodedAudio {
+        sample_rate,
+        channel_count,
+        samples,
+      })
+    }
+    _ => Err(Error::DecodeError("unknown sample rate or channel layout")),
+  }
+}
@@ -0,0 +1,145 @@
+use std::{
+  collections::VecDeque,
+  time::{Duration, Instant},
+};
+
+use super::smoothing::smoothing_factor;
+
+/// Time constant every descriptor is smoothed with
+const SMOOTHING: Duration = Duration::from_millis(100);
+/// Share of the spectral energy below the rolloff frequency
+const ROLLOFF_SHARE: f64 = 0.85;
+/// Keeps silent bins from pulling the flatness to zero
+const MIN_POWER: f64 = 1e-12;
+
+/// Classic timbre descriptors of the spectrum and waveform, smoothed over time.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SpectralDescriptors {
+  /// Magnitude weighted mean frequency in Hz, how bright the sound is
+  pub centroid: f32,
+  /// Frequency in Hz below which 85 % of the spectral energy lies
+  pub rolloff: f32,
+  /// Geometric over arithmetic mean of the power spectrum, 0 for pure tones up to 1 for
+  /// white noise
+  pub flatness: f32,
+  /// Spectral flux the onsets are detected from
+  pub flux: f32,
+  /// Share of consecutive samples with a different sign, from 0 to 1
+  pub zero_crossing_rate: f32,
+}
+
+impl SpectralDescriptors {
+  /// Computes the descriptors of a single frame.
+  ///
+  /// `magnitudes` are the magnitudes of the bins with the center frequencies
+  /// `bin_frequencies`, `channels` the newest samples of every channel.
+  fn measure(
+    magnitudes: &[f32],
+    bin_frequencies: &[f32],
+    channels: &[VecDeque<f32>],
+    flux: f32,
+  ) -> Self {
+    let mut magnitude_sum = 0.0;
+    let mut weighted_frequency_sum = 0.0;
+    let mut power_sum = 0.0;
+    let mut log_power_sum = 0.0;
+    for (magnitude, frequency) in magnitudes.iter().zip(bin_frequencies) {
+      let magnitude = *magnitude as f64;
+      let power = magnitude * magnitude;
+      magnitude_sum += magnitude;
+      weighted_frequency_sum += magnitude * *frequency as f64;
+      power_sum += power;
+      log_power_sum += (power + MIN_POWER).ln();
+    }
+
+    let bin_count = magnitudes.len().min(bin_frequencies.len()).max(1) as f64;
+    let mut descriptors = Self {
+      flux,
+      zero_crossing_rate: zero_crossing_rate(channels),
+      ..Self::default()
+    };
+    if power_sum <= MIN_POWER {
+      return descriptors;
+    }
+
+    descriptors.centroid = (weighted_frequency_sum / magnitude_sum) as f32;
+    descriptors.flatness =
+      ((log_power_sum / bin_count).exp() / (power_sum / bin_count + MIN_POWER)).min(1.0) as f32;
+
+    let mut cumulative_power = 0.0;
+    for (magnitude, frequency) in magnitudes.iter().zip(bin_frequencies) {
+      cumulative_power += *magnitude as f64 * *magnitude as f64;
+      if cumulative_power >= power_sum * ROLLOFF_SHARE {
+        descriptors.rolloff = *frequency;
+        break;
+      }
+    }
+
+    descriptors
+  }
+}
+
+/// Zero crossing rate of all channels together.
+fn zero_crossing_rate(channels: &[VecDeque<f32>]) -> f32 {
+  let (crossings, pairs) = channels.iter().fold((0, 0), |(crossings, pairs), channel| {
+    let channel_crossings = channel
+      .iter()
+      .zip(channel.iter().skip(1))
+      .filter(|(previous, current)| (**previous >= 0.0) != (**current >= 0.0))
+      .count();
+    (
+      crossings + channel_crossings,
+      pairs + channel.len().saturating_sub(1),
+    )
+  });
+
+  if pairs == 0 {
+    return 0.0;
+  }
+  crossings as f32 / pairs as f32
+}
+
+/// Measures the descriptors of every analysis frame and smooths them.
+pub struct DescriptorExtractor {
+  descriptors: Option<SpectralDescriptors>,
+  last_update: Option<Instant>,
+}
+
+impl DescriptorExtractor {
+  pub fn new() -> Self {
+    Self {
+      descriptors: None,
+      last_update: None,
+    }
+  }
+
+  pub fn process(
+    &mut self,
+    magnitudes: &[f32],
+    bin_frequencies: &[f32],
+    channels: &[VecDeque<f32>],
+    flux: f32,
+    now: Instant,
+  ) -> SpectralDescriptors {
+    let elapsed = self
+      .last_update
+      .map_or(0.0, |last_update| (now - last_update).as_secs_f32());
+    self.last_update = Some(now);
+
+    let frame = SpectralDescriptors::measure(magnitudes, bin_frequencies, channels, flux);
+    let Some(descriptors) = &mut self.descriptors else {
+      self.descriptors = Some(frame);
+      return frame;
+    };
+
+    let factor = smoothing_factor(elapsed, SMOOTHING);
+    descriptors.centroid += (frame.centroid - descriptors.centroid) * factor;
+    descriptors.rolloff += (frame.rolloff - descriptors.rolloff) * factor;
+    descriptors.flatness += (frame.flatness - descriptors.flatness) * factor;
+    descriptors.flux += (frame.flux - descriptors.flux) * factor;
+    descriptors.zero_crossing_rate +=
+      (frame.zero_crossing_rate - descriptors.zero_crossing_rate) * factor;
+
+    *descriptors
+  }
+}
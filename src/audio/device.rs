@@ -0,0 +1,367 @@
+use std::{str::FromStr, sync::mpsc};
+
+use cpal::{
+  FromSample, Sample, SampleFormat, SizedSample,
+  traits::{DeviceTrait, HostTrait},
+};
+
+use super::{AudioError, ring_buffer::Producer};
+
+/// Picks a capture device by its position in `--list-devices` or by (part of) its name.
+#[derive(Debug, Clone)]
+pub enum DeviceSelector {
+  Index(usize),
+  Name(String),
+}
+
+impl FromStr for DeviceSelector {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    if value.is_empty() {
+      return Err("device selector can not be empty".to_string());
+    }
+
+    Ok(match value.parse() {
+      Ok(index) => Self::Index(index),
+      Err(_) => Self::Name(value.to_string()),
+    })
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DeviceConfig {
+  /// Name of the cpal host to use, the platform default when `None`
+  pub host: Option<String>,
+  /// Device to capture from, an output monitor or the default input when `None`
+  pub device: Option<DeviceSelector>,
+}
+
+/// Prints every available host and device together with their supported stream configs.
+pub fn list_devices() {
+  let default_host_id = cpal::default_host().id();
+
+  for host_id in cpal::available_hosts() {
+    let default_marker = if host_id == default_host_id {
+      " (default)"
+    } else {
+      ""
+    };
+    println!("Host: {}{}", host_id.name(), default_marker);
+
+    let host = match cpal::host_from_id(host_id) {
+      Ok(host) => host,
+      Err(error) => {
+        println!("  unavailable: {}", error);
+        continue;
+      }
+    };
+
+    let devices = match host.devices() {
+      Ok(devices) => devices,
+      Err(error) => {
+        println!("  failed to enumerate devices: {}", error);
+        continue;
+      }
+    };
+
+    let default_input_name = host
+      .default_input_device()
+      .and_then(|device| device.name().ok());
+
+    for (index, device) in devices.enumerate() {
+      let name = device_name(&device);
+      let default_marker = if default_input_name.as_ref() == Some(&name) {
+        " (default input)"
+      } else {
+        ""
+      };
+      println!("  [{}] {}{}", index, name, default_marker);
+
+      if let Ok(configs) = device.supported_input_configs() {
+        for config in configs {
+          println!("      input:  {}", format_config(&config));
+        }
+      }
+
+      if let Ok(configs) = device.supported_output_configs() {
+        for config in configs {
+          println!("      output: {}", format_config(&config));
+        }
+      }
+    }
+  }
+}
+
+/// Finds the device to capture from according to `config`.
+pub fn find_input_device(config: &DeviceConfig) -> Result<cpal::Device, AudioError> {
+  let host = find_host(config.host.as_deref())?;
+
+  let Some(selector) = &config.device else {
+    return find_output_monitor(&host)
+      .or_else(|| host.default_input_device())
+      .ok_or(AudioError::NoInputDevice);
+  };
+
+  let devices: Vec<cpal::Device> = host.devices().map_err(AudioError::Devices)?.collect();
+
+  let device = match selector {
+    DeviceSelector::Index(index) => devices.into_iter().nth(*index),
+    DeviceSelector::Name(name) => {
+      let lowercase_name = name.to_lowercase();
+      let mut exact_match = None;
+      let mut partial_match = None;
+
+      for device in devices {
+        let device_name = device_name(&device);
+        if device_name == *name {
+          exact_match = Some(device);
+          break;
+        }
+
+        if partial_match.is_none() && device_name.to_lowercase().contains(&lowercase_name) {
+          partial_match = Some(device);
+        }
+      }
+
+      exact_match.or(partial_match)
+    }
+  };
+
+  let Some(device) = device else {
+    return Err(AudioError::DeviceNotFound(selector.clone()));
+  };
+
+  if !device.supports_input() {
+    return Err(AudioError::NoInputSupport(device_name(&device)));
+  }
+
+  if !is_device_supported(&device) {
+    return Err(AudioError::UnsupportedFormat(device_name(&device)));
+  }
+
+  Ok(device)
+}
+
+/// Every capturable device of the host, best candidates first, to fall back to when
+/// the configured device is unavailable.
+pub fn fallback_input_devices(host_name: Option<&str>) -> Vec<cpal::Device> {
+  let Ok(host) = find_host(host_name) else {
+    return Vec::new();
+  };
+
+  let preferred = find_output_monitor(&host)
+    .into_iter()
+    .chain(host.default_input_device());
+  let others = host.input_devices().into_iter().flatten();
+
+  let mut names = Vec::new();
+  let mut devices = Vec::new();
+  for device in preferred.chain(others) {
+    let name = device_name(&device);
+    if is_device_supported(&device) && !names.contains(&name) {
+      names.push(name);
+      devices.push(device);
+    }
+  }
+
+  devices
+}
+
+fn find_host(name: Option<&str>) -> Result<cpal::Host, AudioError> {
+  let Some(name) = name else {
+    return Ok(cpal::default_host());
+  };
+
+  let host_id = cpal::available_hosts()
+    .into_iter()
+    .find(|host_id| host_id.name().eq_ignore_ascii_case(name))
+    .ok_or_else(|| AudioError::UnknownHost(name.to_string()))?;
+
+  cpal::host_from_id(host_id).map_err(AudioError::HostUnavailable)
+}
+
+/// Looks for an output device that can also be captured, which is how most
+/// sound servers expose a monitor of what is currently playing.
+fn find_output_monitor(host: &cpal::Host) -> Option<cpal::Device> {
+  let mut devices = host.output_devices().into_iter().flatten();
+  devices.find(is_device_supported)
+}
+
+/// Whether the host still lists a device named `name`, assumed so when the devices can't be
+/// enumerated.
+pub fn is_device_present(host_name: Option<&str>, name: &str) -> bool {
+  let Ok(host) = find_host(host_name) else {
+    return true;
+  };
+  let Ok(mut devices) = host.devices() else {
+    return true;
+  };
+
+  devices.any(|device| device_name(&device) == name)
+}
+
+pub fn device_name(device: &cpal::Device) -> String {
+  device
+    .name()
+    .unwrap_or_else(|_| "<unknown device>".to_string())
+}
+
+fn format_config(config: &cpal::SupportedStreamConfigRange) -> String {
+  let min_sample_rate = config.min_sample_rate().0;
+  let max_sample_rate = config.max_sample_rate().0;
+
+  let sample_rate = if min_sample_rate == max_sample_rate {
+    format!("{} Hz", min_sample_rate)
+  } else {
+    format!("{}-{} Hz", min_sample_rate, max_sample_rate)
+  };
+
+  format!(
+    "{} ch, {}, {}",
+    config.channels(),
+    sample_rate,
+    config.sample_format()
+  )
+}
+
+/// Sample formats that can be converted to normalized f32 in the stream callback.
+const SUPPORTED_SAMPLE_FORMATS: &[SampleFormat] = &[
+  SampleFormat::F32,
+  SampleFormat::F64,
+  SampleFormat::I8,
+  SampleFormat::I16,
+  SampleFormat::I32,
+  SampleFormat::I64,
+  SampleFormat::U8,
+  SampleFormat::U16,
+  SampleFormat::U32,
+  SampleFormat::U64,
+];
+
+fn is_format_supported(sample_format: SampleFormat) -> bool {
+  SUPPORTED_SAMPLE_FORMATS.contains(&sample_format)
+}
+
+pub fn is_device_supported(device: &cpal::Device) -> bool {
+  if !device.supports_input() {
+    return false;
+  }
+
+  let Ok(mut configs) = device.supported_input_configs() else {
+    return false;
+  };
+
+  configs.any(|config| is_format_supported(config.sample_format()))
+}
+
+/// Chooses the input config to capture with.
+///
+/// The default config is used when it is F32. Otherwise an F32 config with the same
+/// sample rate and channel count is preferred, and any other supported format is
+/// converted in the stream callback.
+pub fn negotiate_input_config(
+  device: &cpal::Device,
+) -> Result<cpal::SupportedStreamConfig, AudioError> {
+  let default_config = device
+    .default_input_config()
+    .map_err(AudioError::DefaultConfig)?;
+
+  if default_config.sample_format() == SampleFormat::F32 {
+    return Ok(default_config);
+  }
+
+  let configs: Vec<_> = device
+    .supported_input_configs()
+    .map_err(AudioError::SupportedConfigs)?
+    .collect();
+
+  let matches_default = |config: &&cpal::SupportedStreamConfigRange| {
+    config.channels() == default_config.channels()
+      && config.min_sample_rate() <= default_config.sample_rate()
+      && config.max_sample_rate() >= default_config.sample_rate()
+  };
+
+  if let Some(config) = configs
+    .iter()
+    .filter(matches_default)
+    .find(|config| config.sample_format() == SampleFormat::F32)
+  {
+    return Ok(config.with_sample_rate(default_config.sample_rate()));
+  }
+
+  if is_format_supported(default_config.sample_format()) {
+    return Ok(default_config);
+  }
+
+  let fallback = configs
+    .into_iter()
+    .filter(|config| is_format_supported(config.sample_format()))
+    .min_by_key(|config| {
+      SUPPORTED_SAMPLE_FORMATS
+        .iter()
+        .position(|format| *format == config.sample_format())
+    });
+
+  match fallback {
+    Some(config) => Ok(
+      config
+        .try_with_sample_rate(default_config.sample_rate())
+        .unwrap_or_else(|| config.with_max_sample_rate()),
+    ),
+    None => Err(AudioError::UnsupportedFormat(device_name(device))),
+  }
+}
+
+/// Opens an input stream that writes every callback's samples as normalized f32 to
+/// `producer`, and sends its errors through `error_tx`.
+pub fn build_input_stream(
+  device: &cpal::Device,
+  config: &cpal::SupportedStreamConfig,
+  producer: Producer,
+  error_tx: mpsc::Sender<cpal::StreamError>,
+) -> Result<cpal::Stream, AudioError> {
+  let stream_config = config.config();
+
+  let stream = match config.sample_format() {
+    SampleFormat::F32 => build_converting_stream::<f32>(device, &stream_config, producer, error_tx),
+    SampleFormat::F64 => build_converting_stream::<f64>(device, &stream_config, producer, error_tx),
+    SampleFormat::I8 => build_converting_stream::<i8>(device, &stream_config, producer, error_tx),
+    SampleFormat::I16 => build_converting_stream::<i16>(device, &stream_config, producer, error_tx),
+    SampleFormat::I32 => build_converting_stream::<i32>(device, &stream_config, producer, error_tx),
+    SampleFormat::I64 => build_converting_stream::<i64>(device, &stream_config, producer, error_tx),
+    SampleFormat::U8 => build_converting_stream::<u8>(device, &stream_config, producer, error_tx),
+    SampleFormat::U16 => build_converting_stream::<u16>(device, &stream_config, producer, error_tx),
+    SampleFormat::U32 => build_converting_stream::<u32>(device, &stream_config, producer, error_tx),
+    SampleFormat::U64 => build_converting_stream::<u64>(device, &stream_config, producer, error_tx),
+    _ => return Err(AudioError::UnsupportedFormat(device_name(device))),
+  };
+
+  stream.map_err(AudioError::BuildStream)
+}
+
+fn build_converting_stream<T>(
+  device: &cpal::Device,
+  stream_config: &cpal::StreamConfig,
+  mut producer: Producer,
+  error_tx: mpsc::Sender<cpal::StreamError>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+  T: SizedSample,
+  f32: FromSample<T>,
+{
+  device.build_input_stream(
+    stream_config,
+    move |data: &[T], _: &_| {
+      // The buffer is only full when the analyzer stopped reading, so dropping is fine
+      producer.push(
+        data.len(),
+        data.iter().map(|sample| f32::from_sample(*sample)),
+      );
+    },
+    move |error| {
+      let _ = error_tx.send(error);
+    },
+    None,
+  )
+}
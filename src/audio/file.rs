@@ -0,0 +1,525 @@
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  str::FromStr,
+  sync::{Arc, Mutex},
+  time::Instant,
+};
+
+use cpal::{
+  FromSample, SampleFormat, SizedSample,
+  traits::{DeviceTrait, HostTrait, StreamTrait},
+};
+
+use super::{
+  AudioError, AudioSource, PlaybackCommand,
+  decoder::{self, DecodedAudio},
+  device,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoopMode {
+  #[default]
+  Off,
+  Track,
+  Playlist,
+}
+
+impl LoopMode {
+  fn next(self) -> Self {
+    match self {
+      Self::Off => Self::Track,
+      Self::Track => Self::Playlist,
+      Self::Playlist => Self::Off,
+    }
+  }
+}
+
+impl FromStr for LoopMode {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "off" => Ok(Self::Off),
+      "track" => Ok(Self::Track),
+      "playlist" => Ok(Self::Playlist),
+      _ => Err(format!(
+        "unknown loop mode `{}`, expected off, track or playlist",
+        value
+      )),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FileConfig {
+  /// Audio files or `.m3u` playlists, played in order
+  pub paths: Vec<PathBuf>,
+  /// Also play the decoded audio through the default output device
+  pub playback: bool,
+  pub loop_mode: LoopMode,
+}
+
+/// Playback state shared with the output stream callback.
+struct Transport {
+  samples: Vec<f32>,
+  position: usize,
+  paused: bool,
+}
+
+impl Transport {
+  fn frame_count(&self, channel_count: usize) -> usize {
+    self.samples.len() / channel_count
+  }
+}
+
+/// Decodes audio files and feeds them to the analyzer at real-time pace.
+///
+/// Every track is converted to one sample rate and channel count, which is the
+/// output device's when the audio is played out loud and the first track's otherwise.
+pub struct FileSource {
+  playlist: Vec<PathBuf>,
+  track_index: usize,
+  loop_mode: LoopMode,
+  sample_rate: u32,
+  channel_count: usize,
+  transport: Arc<Mutex<Transport>>,
+  /// Frames of the current track that were already handed to the analyzer
+  read_position: usize,
+  /// Advances the playback position when there is no output stream doing so
+  clock: Option<Instant>,
+  clock_remainder: f64,
+  _output_stream: Option<cpal::Stream>,
+}
+
+impl FileSource {
+  pub fn open(config: &FileConfig) -> Result<Self, AudioError> {
+    let playlist = expand_playlist(&config.paths)?;
+    let first_track = decode_file(&playlist[0])?;
+
+    let transport = Arc::new(Mutex::new(Transport {
+      samples: Vec::new(),
+      position: 0,
+      paused: false,
+    }));
+
+    let (sample_rate, channel_count, output_stream) = if config.playback {
+      let (stream, stream_config) = open_output_stream(transport.clone())?;
+      (
+        stream_config.sample_rate.0,
+        stream_config.channels as usize,
+        Some(stream),
+      )
+    } else {
+      (first_track.sample_rate, first_track.channel_count, None)
+    };
+
+    let mut source = Self {
+      playlist,
+      track_index: 0,
+      loop_mode: config.loop_mode,
+      sample_rate,
+      channel_count,
+      transport,
+      read_position: 0,
+      clock: (!config.playback).then(Instant::now),
+      clock_remainder: 0.0,
+      _output_stream: output_stream,
+    };
+
+    if !source.set_track(0, first_track) {
+      source.load_track(1 % source.playlist.len());
+    }
+    Ok(source)
+  }
+
+  /// Switches to `decoded`, unless it has no frames to play.
+  fn set_track(&mut self, track_index: usize, decoded: DecodedAudio) -> bool {
+    let path = &self.playlist[track_index];
+    let samples = convert(&decoded, self.sample_rate, self.channel_count);
+    if samples.is_empty() {
+      log::error!("skipping track: {} contains no audio", path.display());
+      return false;
+    }
+    log::info!("playing {}", path.display());
+
+    let mut transport = self.transport.lock().unwrap();
+    transport.samples = samples;
+    transport.position = 0;
+
+    self.track_index = track_index;
+    self.read_position = 0;
+    true
+  }
+
+  /// Loads the track at `track_index`, skipping tracks that fail to decode or are empty.
+  ///
+  /// Pauses when no track can be played, so the end of the track isn't reached again on
+  /// every read.
+  fn load_track(&mut self, track_index: usize) {
+    for offset in 0..self.playlist.len() {
+      let index = (track_index + offset) % self.playlist.len();
+      match decode_file(&self.playlist[index]) {
+        Ok(decoded) => {
+          if self.set_track(index, decoded) {
+            return;
+          }
+        }
+        Err(error) => log::error!("skipping track: {}", error),
+      }
+    }
+
+    log::error!("no track of the playlist can be played");
+    let mut transport = self.transport.lock().unwrap();
+    transport.samples.clear();
+    transport.position = 0;
+    transport.paused = true;
+    self.read_position = 0;
+  }
+
+  fn finish_track(&mut self) {
+    let next_index = self.track_index + 1;
+    match self.loop_mode {
+      LoopMode::Track => self.seek_to(0),
+      LoopMode::Playlist => self.load_track(next_index % self.playlist.len()),
+      LoopMode::Off if next_index < self.playlist.len() => self.load_track(next_index),
+      LoopMode::Off => self.transport.lock().unwrap().paused = true,
+    }
+  }
+
+  fn seek_to(&mut self, position: usize) {
+    let mut transport = self.transport.lock().unwrap();
+    let position = position.min(transport.frame_count(self.channel_count));
+    transport.position = position;
+    self.read_position = position;
+  }
+
+  fn seek_by(&mut self, seconds: f32) {
+    let offset = (seconds.abs() * self.sample_rate as f32) as usize;
+    let position = self.transport.lock().unwrap().position;
+
+    if seconds < 0.0 {
+      self.seek_to(position.saturating_sub(offset));
+    } else {
+      self.seek_to(position + offset);
+    }
+  }
+
+  fn toggle_pause(&mut self) {
+    let mut transport = self.transport.lock().unwrap();
+    // Nothing to resume when no track could be played
+    if transport.samples.is_empty() {
+      return;
+    }
+    let at_end = transport.position >= transport.frame_count(self.channel_count);
+
+    transport.paused = !transport.paused;
+    // Resuming after the last track ended starts it over
+    if !transport.paused && at_end {
+      transport.position = 0;
+      self.read_position = 0;
+    }
+  }
+
+  fn advance_clock(&mut self, transport: &mut Transport) {
+    let Some(clock) = &mut self.clock else {
+      return;
+    };
+
+    let elapsed = clock.elapsed().as_secs_f64();
+    *clock = Instant::now();
+
+    if transport.paused {
+      return;
+    }
+
+    let frames = elapsed * self.sample_rate as f64 + self.clock_remainder;
+    self.clock_remainder = frames.fract();
+    transport.position =
+      (transport.position + frames as usize).min(transport.frame_count(self.channel_count));
+  }
+}
+
+impl AudioSource for FileSource {
+  fn sample_rate(&self) -> u32 {
+    self.sample_rate
+  }
+
+  fn channel_count(&self) -> usize {
+    self.channel_count
+  }
+
+  fn read_frames(&mut self, buffer: &mut Vec<f32>, max_frames: usize) -> usize {
+    let transport = self.transport.clone();
+    let mut transport = transport.lock().unwrap();
+    self.advance_clock(&mut transport);
+
+    let end = transport.position;
+    let start = self
+      .read_position
+      .max(end.saturating_sub(max_frames))
+      .min(end);
+    buffer
+      .extend_from_slice(&transport.samples[start * self.channel_count..end * self.channel_count]);
+    self.read_position = end;
+
+    let finished = !transport.paused && end >= transport.frame_count(self.channel_count);
+    drop(transport);
+
+    if finished {
+      self.finish_track();
+    }
+
+    end - start
+  }
+
+  fn control(&mut self, command: PlaybackCommand) {
+    match command {
+      PlaybackCommand::TogglePause => self.toggle_pause(),
+      PlaybackCommand::SeekBy(seconds) => self.seek_by(seconds),
+      PlaybackCommand::CycleLoopMode => {
+        self.loop_mode = self.loop_mode.next();
+        log::info!("loop mode: {:?}", self.loop_mode);
+      }
+      PlaybackCommand::NextTrack => self.load_track((self.track_index + 1) % self.playlist.len()),
+      PlaybackCommand::PreviousTrack => {
+        self.load_track((self.track_index + self.playlist.len() - 1) % self.playlist.len())
+      }
+    }
+  }
+}
+
+/// Replaces `.m3u` playlists with the files they list, relative to the playlist itself.
+fn expand_playlist(paths: &[PathBuf]) -> Result<Vec<PathBuf>, AudioError> {
+  let mut playlist = Vec::new();
+
+  for path in paths {
+    let is_playlist = path.extension().is_some_and(|extension| {
+      extension.eq_ignore_ascii_case("m3u") || extension.eq_ignore_ascii_case("m3u8")
+    });
+
+    if !is_playlist {
+      playlist.push(path.clone());
+      continue;
+    }
+
+    let contents =
+      fs::read_to_string(path).map_err(|error| AudioError::OpenFile(path.clone(), error))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    playlist.extend(
+      contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line)),
+    );
+  }
+
+  if playlist.is_empty() {
+    return Err(AudioError::EmptyPlaylist);
+  }
+
+  Ok(playlist)
+}
+
+fn decode_file(path: &Path) -> Result<DecodedAudio, AudioError> {
+  let bytes = fs::read(path).map_err(|error| AudioError::OpenFile(path.to_path_buf(), error))?;
+
+  let extension = path.extension().and_then(|extension| extension.to_str());
+  decoder::decode(bytes, extension).map_err(|error| match error {
+    symphonia::core::errors::Error::Unsupported(_) => {
+      AudioError::UnsupportedFile(path.to_path_buf())
+    }
+    error => AudioError::DecodeFile(path.to_path_buf(), error),
+  })
+}
+
+/// Converts decoded audio to the given sample rate and channel count.
+///
+/// Channels are mixed down or repeated as needed and the sample rate is changed
+/// with linear interpolation, which is plenty for visualizing.
+fn convert(decoded: &DecodedAudio, sample_rate: u32, channel_count: usize) -> Vec<f32> {
+  let source_channels = decoded.channel_count;
+  let source_frames = decoded.samples.len() / source_channels;
+  if source_frames == 0 {
+    return Vec::new();
+  }
+
+  let frame_sample = |frame: usize, channel: usize| -> f32 {
+    let frame = &decoded.samples[frame * source_channels..(frame + 1) * source_channels];
+    if channel_count == 1 {
+      frame.iter().sum::<f32>() / source_channels as f32
+    } else {
+      frame[channel.min(source_channels - 1)]
+    }
+  };
+
+  let ratio = decoded.sample_rate as f64 / sample_rate as f64;
+  let frame_count = (source_frames as f64 / ratio) as usize;
+  let mut samples = Vec::with_capacity(frame_count * channel_count);
+
+  for frame in 0..frame_count {
+    let position = frame as f64 * ratio;
+    let index = position as usize;
+    let next = (index + 1).min(source_frames - 1);
+    let fraction = position.fract() as f32;
+
+    for channel in 0..channel_count {
+      let current = frame_sample(index, channel);
+      samples.push(current + (frame_sample(next, channel) - current) * fraction);
+    }
+  }
+
+  samples
+}
+
+fn open_output_stream(
+  transport: Arc<Mutex<Transport>>,
+) -> Result<(cpal::Stream, cpal::StreamConfig), AudioError> {
+  let device = cpal::default_host()
+    .default_output_device()
+    .ok_or(AudioError::NoOutputDevice)?;
+  let supported_config = device
+    .default_output_config()
+    .map_err(AudioError::DefaultConfig)?;
+  let stream_config = supported_config.config();
+  let channel_count = stream_config.channels as usize;
+
+  let stream = match supported_config.sample_format() {
+    SampleFormat::F32 => build_output_stream::<f32>(&device, &stream_config, transport),
+    SampleFormat::F64 => build_output_stream::<f64>(&device, &stream_config, transport),
+    SampleFormat::I16 => build_output_stream::<i16>(&device, &stream_config, transport),
+    SampleFormat::I32 => build_output_stream::<i32>(&device, &stream_config, transport),
+    SampleFormat::U16 => build_output_stream::<u16>(&device, &stream_config, transport),
+    _ => return Err(AudioError::UnsupportedFormat(device::device_name(&device))),
+  }
+  .map_err(AudioError::BuildStream)?;
+
+  stream.play().map_err(AudioError::PlayStream)?;
+  log::info!(
+    "playing through {} at {} Hz with {} channels",
+    device::device_name(&device),
+    stream_config.sample_rate.0,
+    channel_count
+  );
+
+  Ok((stream, stream_config))
+}
+
+fn build_output_stream<T>(
+  device: &cpal::Device,
+  stream_config: &cpal::StreamConfig,
+  transport: Arc<Mutex<Transport>>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+  T: SizedSample + FromSample<f32>,
+{
+  let channel_count = stream_config.channels as usize;
+
+  device.build_output_stream(
+    stream_config,
+    move |data: &mut [T], _: &_| {
+      let silence = T::from_sample(0.0f32);
+
+      // Never block the audio thread, a missed lock only costs a buffer of silence
+      let Ok(mut transport) = transport.try_lock() else {
+        data.fill(silence);
+        return;
+      };
+
+      if transport.paused {
+        data.fill(silence);
+        return;
+      }
+
+      let start = transport.position * channel_count;
+      let end = (start + data.len()).min(transport.samples.len());
+      let len = end.saturating_sub(start);
+
+      for (output, sample) in data.iter_mut().zip(&transport.samples[start.min(end)..end]) {
+        *output = T::from_sample(*sample);
+      }
+      data[len..].fill(silence);
+
+      transport.position += len / channel_count;
+    },
+    |error| log::error!("an error occurred on the output stream: {}", error),
+    None,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{env, process};
+
+  use super::*;
+
+  /// Writes a 16 bit mono WAV file at 8 kHz into a directory of its own for this test run.
+  fn write_wav(name: &str, samples: &[i16]) -> PathBuf {
+    let directory = env::temp_dir().join(format!("julia-visualizer-{}", process::id()));
+    fs::create_dir_all(&directory).unwrap();
+
+    let data: Vec<u8> = samples
+      .iter()
+      .flat_map(|sample| sample.to_le_bytes())
+      .collect();
+    let mut bytes = Vec::new();
+    bytes.extend(b"RIFF");
+    bytes.extend((36 + data.len() as u32).to_le_bytes());
+    bytes.extend(b"WAVEfmt ");
+    bytes.extend(16u32.to_le_bytes());
+    // PCM, mono, 8 kHz, 16000 bytes per second, 2 bytes per frame, 16 bits per sample
+    for field in [1u16, 1] {
+      bytes.extend(field.to_le_bytes());
+    }
+    bytes.extend(8000u32.to_le_bytes());
+    bytes.extend(16000u32.to_le_bytes());
+    for field in [2u16, 16] {
+      bytes.extend(field.to_le_bytes());
+    }
+    bytes.extend(b"data");
+    bytes.extend((data.len() as u32).to_le_bytes());
+    bytes.extend(data);
+
+    let path = directory.join(name);
+    fs::write(&path, bytes).unwrap();
+    path
+  }
+
+  fn open(paths: Vec<PathBuf>, loop_mode: LoopMode) -> FileSource {
+    FileSource::open(&FileConfig {
+      paths,
+      playback: false,
+      loop_mode,
+    })
+    .unwrap()
+  }
+
+  #[test]
+  fn skips_empty_tracks() {
+    let tone = write_wav("tone.wav", &[1000; 800]);
+    let empty = write_wav("skipped.wav", &[]);
+    let mut source = open(vec![tone, empty], LoopMode::Playlist);
+
+    source.control(PlaybackCommand::NextTrack);
+    assert_eq!(source.track_index, 0);
+    assert!(!source.transport.lock().unwrap().samples.is_empty());
+  }
+
+  #[test]
+  fn pauses_when_only_empty_tracks_are_left() {
+    let empty = write_wav("empty.wav", &[]);
+
+    for loop_mode in [LoopMode::Off, LoopMode::Track, LoopMode::Playlist] {
+      let mut source = open(vec![empty.clone()], loop_mode);
+      assert!(source.transport.lock().unwrap().paused);
+
+      // Resuming has nothing to play either
+      source.control(PlaybackCommand::TogglePause);
+      let mut buffer = Vec::new();
+      assert_eq!(source.read_frames(&mut buffer, 1024), 0);
+      assert!(source.transport.lock().unwrap().paused);
+    }
+  }
+}
@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+
+use super::smoothing::smoothing_factor;
+
+/// Below this short-term loudness in LUFS the input counts as silence and the gain is held,
+/// so noise between tracks is not pulled up
+const SILENCE_LOUDNESS: f32 = -60.0;
+/// How many times faster the gain falls than it rises, so a loud part after a quiet one
+/// does not saturate the visuals for long
+const FALL_SPEEDUP: u32 = 4;
+
+#[derive(Debug, Clone)]
+pub struct GainConfig {
+  /// Off bypasses the gain control and passes the spectrum and waveform on unchanged
+  pub enabled: bool,
+  /// Short-term loudness in LUFS the input is normalized to
+  pub target: f32,
+  /// Time constant for following a change in loudness
+  pub speed: Duration,
+  /// Lowest gain in dB
+  pub min_gain: f32,
+  /// Highest gain in dB
+  pub max_gain: f32,
+}
+
+impl Default for GainConfig {
+  fn default() -> Self {
+    Self {
+      enabled: true,
+      target: -18.0,
+      speed: Duration::from_secs(3),
+      min_gain: -12.0,
+      max_gain: 24.0,
+    }
+  }
+}
+
+/// Automatic gain control that follows the long-term loudness, so quiet and loud
+/// recordings move the visuals alike.
+pub struct AutoGain {
+  config: GainConfig,
+  /// Current gain in dB
+  gain: f32,
+  last_update: Option<Instant>,
+}
+
+impl AutoGain {
+  pub fn new(config: GainConfig) -> Self {
+    Self {
+      config,
+      gain: 0.0,
+      last_update: None,
+    }
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.config.enabled
+  }
+
+  pub fn set_enabled(&mut self, enabled: bool) {
+    self.config.enabled = enabled;
+  }
+
+  /// Moves the gain towards the one that brings `loudness` to the target and returns it
+  /// as a linear factor, 1 while bypassed.
+  pub fn process(&mut self, loudness: f32, now: Instant) -> f32 {
+    let elapsed = self
+      .last_update
+      .map_or(0.0, |last_update| (now - last_update).as_secs_f32());
+    self.last_update = Some(now);
+
+    if loudness > SILENCE_LOUDNESS {
+      let target_gain =
+        (self.config.target - loudness).clamp(self.config.min_gain, self.config.max_gain);
+      let speed = if target_gain < self.gain {
+        self.config.speed / FALL_SPEEDUP
+      } else {
+        self.config.speed
+      };
+      self.gain += (target_gain - self.gain) * smoothing_factor(elapsed, speed);
+    }
+
+    if !self.config.enabled {
+      return 1.0;
+    }
+
+    10f32.powf(self.gain / 20.0)
+  }
+}
@@ -0,0 +1,270 @@
+use std::{f64::consts::TAU, str::FromStr, time::Instant};
+
+use super::AudioSource;
+
+const SAMPLE_RATE: u32 = 48000;
+const AMPLITUDE: f32 = 0.5;
+
+/// Test signals the generator can produce.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Signal {
+  Sine {
+    frequency: f64,
+  },
+  /// Logarithmic sweep from `start` to `end` Hz over `duration` seconds, then repeats
+  Sweep {
+    start: f64,
+    end: f64,
+    duration: f64,
+  },
+  WhiteNoise,
+  PinkNoise,
+  /// Single sample clicks at `bpm` beats per minute
+  Impulses {
+    bpm: f64,
+  },
+  /// Equal level sines at every frequency
+  Chord {
+    frequencies: Vec<f64>,
+  },
+}
+
+impl FromStr for Signal {
+  type Err = String;
+
+  /// Parses `sine:<hz>`, `sweep:<start hz>:<end hz>:<seconds>`, `white`, `pink`,
+  /// `impulse:<bpm>` or `chord:<hz>,<hz>,...`.
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    let (name, parameters) = value.split_once(':').unwrap_or((value, ""));
+    let parameters: Vec<f64> = parameters
+      .split([':', ','])
+      .filter(|parameter| !parameter.is_empty())
+      .map(|parameter| match parameter.parse() {
+        Ok(number) if number > 0.0 => Ok(number),
+        _ => Err(format!("invalid signal parameter `{}`", parameter)),
+      })
+      .collect::<Result<_, _>>()?;
+
+    let signal = match (name, parameters.as_slice()) {
+      ("sine", [frequency]) => Self::Sine {
+        frequency: *frequency,
+      },
+      ("sweep", [start, end, duration]) => Self::Sweep {
+        start: *start,
+        end: *end,
+        duration: *duration,
+      },
+      ("white", []) => Self::WhiteNoise,
+      ("pink", []) => Self::PinkNoise,
+      ("impulse", [bpm]) => Self::Impulses { bpm: *bpm },
+      ("chord", frequencies) if !frequencies.is_empty() => Self::Chord {
+        frequencies: frequencies.to_vec(),
+      },
+      _ => {
+        return Err(format!(
+          "invalid signal `{}`, expected sine:<hz>, sweep:<start>:<end>:<seconds>, white, \
+           pink, impulse:<bpm> or chord:<hz>,<hz>,...",
+          value
+        ));
+      }
+    };
+
+    Ok(signal)
+  }
+}
+
+/// How the signal is placed in the two output channels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StereoMode {
+  /// The same signal in both channels
+  #[default]
+  Mono,
+  Left,
+  Right,
+  /// The right channel is the inverted left channel
+  AntiPhase,
+  /// Each channel runs its own generator: independent noise, tones a quarter period
+  /// apart and impulses alternating between the channels
+  Decorrelated,
+}
+
+impl FromStr for StereoMode {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "mono" => Ok(Self::Mono),
+      "left" => Ok(Self::Left),
+      "right" => Ok(Self::Right),
+      "anti-phase" => Ok(Self::AntiPhase),
+      "decorrelated" => Ok(Self::Decorrelated),
+      _ => Err(format!(
+        "unknown stereo mode `{}`, expected mono, left, right, anti-phase or decorrelated",
+        value
+      )),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+  pub signal: Signal,
+  pub stereo: StereoMode,
+}
+
+/// Per channel generator state.
+struct Voice {
+  /// Offset in periods for tones and in beats for impulses
+  phase_offset: f64,
+  rng_state: u32,
+  pink_state: [f32; 7],
+}
+
+impl Voice {
+  fn new(phase_offset: f64, seed: u32) -> Self {
+    Self {
+      phase_offset,
+      rng_state: seed,
+      pink_state: [0.0; 7],
+    }
+  }
+
+  /// Uniform white noise in -1..1 from a xorshift generator, so runs are reproducible.
+  fn white_noise(&mut self) -> f32 {
+    self.rng_state ^= self.rng_state << 13;
+    self.rng_state ^= self.rng_state >> 17;
+    self.rng_state ^= self.rng_state << 5;
+    self.rng_state as f32 / u32::MAX as f32 * 2.0 - 1.0
+  }
+
+  /// Paul Kellet's pink noise filter applied to white noise.
+  fn pink_noise(&mut self) -> f32 {
+    let white = self.white_noise();
+    let b = &mut self.pink_state;
+    b[0] = 0.99886 * b[0] + white * 0.0555179;
+    b[1] = 0.99332 * b[1] + white * 0.0750759;
+    b[2] = 0.96900 * b[2] + white * 0.153852;
+    b[3] = 0.86650 * b[3] + white * 0.3104856;
+    b[4] = 0.55000 * b[4] + white * 0.5329522;
+    b[5] = -0.7616 * b[5] - white * 0.0168980;
+    let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+    b[6] = white * 0.115926;
+    pink * 0.11
+  }
+
+  fn sample(&mut self, signal: &Signal, frame: u64) -> f32 {
+    let time = frame as f64 / SAMPLE_RATE as f64;
+
+    match signal {
+      Signal::Sine { frequency } => ((time * frequency + self.phase_offset) * TAU).sin() as f32,
+      Signal::Sweep {
+        start,
+        end,
+        duration,
+      } => {
+        // Phase of an exponential chirp, the integral of start * (end / start)^(t / duration)
+        let time = time % duration;
+        let rate = (end / start).ln() / duration;
+        let phase = if rate.abs() < f64::EPSILON {
+          start * time
+        } else {
+          start * ((rate * time).exp() - 1.0) / rate
+        };
+        ((phase + self.phase_offset) * TAU).sin() as f32
+      }
+      Signal::WhiteNoise => self.white_noise(),
+      Signal::PinkNoise => self.pink_noise(),
+      Signal::Impulses { bpm } => {
+        let period = (60.0 / bpm * SAMPLE_RATE as f64).max(1.0) as u64;
+        let offset = (self.phase_offset * period as f64) as u64;
+        if (frame + period - offset % period).is_multiple_of(period) {
+          1.0
+        } else {
+          0.0
+        }
+      }
+      Signal::Chord { frequencies } => {
+        let sum: f64 = frequencies
+          .iter()
+          .map(|frequency| ((time * frequency + self.phase_offset) * TAU).sin())
+          .sum();
+        (sum / frequencies.len() as f64) as f32
+      }
+    }
+  }
+}
+
+/// Deterministic stereo test signals, produced at real-time pace.
+pub struct SignalGenerator {
+  config: GeneratorConfig,
+  left: Voice,
+  right: Voice,
+  frame: u64,
+  clock: Instant,
+  clock_remainder: f64,
+}
+
+impl SignalGenerator {
+  pub fn new(config: GeneratorConfig) -> Self {
+    let right_phase_offset = match (&config.signal, config.stereo) {
+      (Signal::Impulses { .. }, StereoMode::Decorrelated) => 0.5,
+      (_, StereoMode::Decorrelated) => 0.25,
+      _ => 0.0,
+    };
+
+    Self {
+      config,
+      left: Voice::new(0.0, 0x9E3779B9),
+      right: Voice::new(right_phase_offset, 0x85EBCA6B),
+      frame: 0,
+      clock: Instant::now(),
+      clock_remainder: 0.0,
+    }
+  }
+
+  fn next_frame(&mut self) -> [f32; 2] {
+    let signal = &self.config.signal;
+    let left = self.left.sample(signal, self.frame);
+    self.frame += 1;
+
+    let frame = match self.config.stereo {
+      StereoMode::Mono => [left, left],
+      StereoMode::Left => [left, 0.0],
+      StereoMode::Right => [0.0, left],
+      StereoMode::AntiPhase => [left, -left],
+      StereoMode::Decorrelated => [left, self.right.sample(signal, self.frame - 1)],
+    };
+
+    frame.map(|sample| sample * AMPLITUDE)
+  }
+}
+
+impl AudioSource for SignalGenerator {
+  fn sample_rate(&self) -> u32 {
+    SAMPLE_RATE
+  }
+
+  fn channel_count(&self) -> usize {
+    2
+  }
+
+  fn read_frames(&mut self, buffer: &mut Vec<f32>, max_frames: usize) -> usize {
+    let elapsed = self.clock.elapsed().as_secs_f64();
+    self.clock = Instant::now();
+
+    // After a long stall only generate the last second instead of catching up
+    let frames = (elapsed * SAMPLE_RATE as f64 + self.clock_remainder).min(SAMPLE_RATE as f64);
+    self.clock_remainder = frames.fract();
+
+    // Frames that would be dropped anyway are skipped instead of generated
+    let frame_count = (frames as usize).min(max_frames);
+    self.frame += frames as u64 - frame_count as u64;
+
+    buffer.reserve(frame_count * 2);
+    for _ in 0..frame_count {
+      buffer.extend(self.next_frame());
+    }
+
+    frame_count
+  }
+}
@@ -0,0 +1,170 @@
+use std::{ops::Range, str::FromStr};
+
+/// How frequencies are distributed over the bands of the spectrum sent to the renderer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SpectrumLayout {
+  /// Equally wide bands in Hz
+  #[default]
+  Linear,
+  /// Equally wide bands in octaves
+  Log,
+  Mel,
+  Bark,
+  /// Standard 1/3-octave bands, their count follows from the frequency range
+  ThirdOctave,
+}
+
+impl SpectrumLayout {
+  /// Maps a frequency onto a scale in which the bands are equally wide.
+  fn frequency_to_scale(self, frequency: f32) -> f32 {
+    match self {
+      Self::Linear => frequency,
+      Self::Log | Self::ThirdOctave => frequency.ln(),
+      Self::Mel => 2595.0 * (1.0 + frequency / 700.0).log10(),
+      // Traunmüller's approximation
+      Self::Bark => 26.81 * frequency / (1960.0 + frequency) - 0.53,
+    }
+  }
+
+  fn scale_to_frequency(self, value: f32) -> f32 {
+    match self {
+      Self::Linear => value,
+      Self::Log | Self::ThirdOctave => value.exp(),
+      Self::Mel => 700.0 * (10f32.powf(value / 2595.0) - 1.0),
+      Self::Bark => 1960.0 * (value + 0.53) / (26.28 - value),
+    }
+  }
+
+  /// Edges of `band_count` bands between `min` and `max` Hz, one more than there are bands.
+  fn band_edges(self, min: f32, max: f32, band_count: usize) -> Vec<f32> {
+    if self == Self::ThirdOctave {
+      // Centers at 1 kHz * 2^(n/3), every band spans a sixth of an octave to each side
+      let first = (3.0 * (min / 1000.0).log2()).ceil() as i32;
+      let last = (3.0 * (max / 1000.0).log2()).floor() as i32;
+      return (first..=last + 1)
+        .map(|band| 1000.0 * 2f32.powf((band as f32 - 0.5) / 3.0))
+        .collect();
+    }
+
+    let min_scale = self.frequency_to_scale(min);
+    let max_scale = self.frequency_to_scale(max);
+    (0..=band_count)
+      .map(|edge| {
+        let position = edge as f32 / band_count as f32;
+        self.scale_to_frequency(min_scale + (max_scale - min_scale) * position)
+      })
+      .collect()
+  }
+}
+
+impl FromStr for SpectrumLayout {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "linear" => Ok(Self::Linear),
+      "log" => Ok(Self::Log),
+      "mel" => Ok(Self::Mel),
+      "bark" => Ok(Self::Bark),
+      "third-octave" => Ok(Self::ThirdOctave),
+      _ => Err(format!(
+        "unknown spectrum layout `{}`, expected linear, log, mel, bark or third-octave",
+        value
+      )),
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct LayoutConfig {
+  pub layout: SpectrumLayout,
+  pub min_frequency: f32,
+  pub max_frequency: f32,
+  /// Number of bands, one per pixel of the window width when `None`
+  pub band_count: Option<usize>,
+}
+
+impl Default for LayoutConfig {
+  fn default() -> Self {
+    Self {
+      layout: SpectrumLayout::default(),
+      min_frequency: 20.0,
+      max_frequency: 20000.0,
+      band_count: None,
+    }
+  }
+}
+
+struct Band {
+  /// Bins whose frequency lies inside the band
+  bins: Range<usize>,
+  /// Fractional bin index of the band center, read when the band is narrower than a bin
+  center: f32,
+}
+
+/// Precomputed assignment of analysis bins to the bands of a layout.
+pub struct BandMap {
+  bands: Vec<Band>,
+}
+
+impl BandMap {
+  /// `bin_frequencies` are the ascending center frequencies of the analysis bins.
+  pub fn new(config: &LayoutConfig, bin_frequencies: &[f32], band_count: usize) -> Self {
+    let nyquist = bin_frequencies.last().copied().unwrap_or(0.0);
+    let max = config.max_frequency.min(nyquist);
+    let min = config.min_frequency.clamp(1.0, max.max(1.0));
+
+    let edges = config.layout.band_edges(min, max, band_count.max(1));
+    let bands = edges
+      .windows(2)
+      .map(|edge| {
+        let start = bin_frequencies.partition_point(|frequency| *frequency < edge[0]);
+        let end = bin_frequencies.partition_point(|frequency| *frequency < edge[1]);
+        Band {
+          bins: start..end,
+          center: fractional_bin(bin_frequencies, (edge[0] * edge[1]).sqrt()),
+        }
+      })
+      .collect();
+
+    Self { bands }
+  }
+
+  /// Reduces the magnitude of every bin to the loudest one of each band.
+  pub fn apply(&self, magnitudes: &[f32]) -> Vec<f32> {
+    self
+      .bands
+      .iter()
+      .map(|band| {
+        if !band.bins.is_empty() {
+          return magnitudes[band.bins.clone()]
+            .iter()
+            .copied()
+            .fold(0.0, f32::max);
+        }
+
+        let index = (band.center as usize).min(magnitudes.len().saturating_sub(1));
+        let next = (index + 1).min(magnitudes.len().saturating_sub(1));
+        match (magnitudes.get(index), magnitudes.get(next)) {
+          (Some(current), Some(next)) => current + (next - current) * band.center.fract(),
+          _ => 0.0,
+        }
+      })
+      .collect()
+  }
+}
+
+/// Position of `frequency` between the bins around it.
+fn fractional_bin(bin_frequencies: &[f32], frequency: f32) -> f32 {
+  let next = bin_frequencies.partition_point(|bin_frequency| *bin_frequency < frequency);
+  if next == 0 {
+    return 0.0;
+  }
+  if next >= bin_frequencies.len() {
+    return bin_frequencies.len().saturating_sub(1) as f32;
+  }
+
+  let low = bin_frequencies[next - 1];
+  let high = bin_frequencies[next];
+  (next - 1) as f32 + (frequency - low) / (high - low)
+}
@@ -0,0 +1,529 @@
+use std::{collections::VecDeque, f64::consts::PI};
+
+/// Loudness in LUFS reported for silence, the absolute gate of EBU R128
+const MIN_LOUDNESS: f32 = -70.0;
+/// Upper end of the range integrated loudness is measured in
+const MAX_LOUDNESS: f32 = 10.0;
+/// Resolution in LU of the histogram integrated loudness is gated with
+const HISTOGRAM_STEP: f32 = 0.1;
+/// Integrated loudness ignores blocks more than 10 LU below the average
+const RELATIVE_GATE: f32 = -10.0;
+/// Loudness is measured in blocks of 100 ms, the windows span several of them
+const BLOCKS_PER_SECOND: u32 = 10;
+const MOMENTARY_BLOCKS: usize = 4;
+const SHORT_TERM_BLOCKS: usize = 30;
+/// Taps of every phase of the interpolation filter used to find the true peak
+const TRUE_PEAK_TAPS: usize = 12;
+
+/// Level and loudness of one channel, or of all of them together.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoudnessLevels {
+  /// Linear RMS level over the momentary window
+  pub rms: f32,
+  /// Highest linear level between the samples over the momentary window
+  pub true_peak: f32,
+  /// K-weighted loudness in LUFS over 400 ms
+  pub momentary: f32,
+  /// K-weighted loudness in LUFS over 3 s
+  pub short_term: f32,
+  /// Gated K-weighted loudness in LUFS since the start
+  pub integrated: f32,
+}
+
+impl Default for LoudnessLevels {
+  fn default() -> Self {
+    Self {
+      rms: 0.0,
+      true_peak: 0.0,
+      momentary: MIN_LOUDNESS,
+      short_term: MIN_LOUDNESS,
+      integrated: MIN_LOUDNESS,
+    }
+  }
+}
+
+/// Loudness of every source channel and of the whole program, following EBU R128.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Loudness {
+  pub channels: Vec<LoudnessLevels>,
+  pub program: LoudnessLevels,
+}
+
+/// Converts a mean square K-weighted level to LUFS.
+fn energy_to_loudness(energy: f64) -> f32 {
+  if energy <= 0.0 {
+    return MIN_LOUDNESS;
+  }
+
+  (-0.691 + 10.0 * energy.log10() as f32).max(MIN_LOUDNESS)
+}
+
+/// Second order IIR filter in transposed direct form II.
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+  b: [f64; 3],
+  a: [f64; 2],
+  state: [f64; 2],
+}
+
+impl Biquad {
+  fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+    Self {
+      b,
+      a,
+      state: [0.0; 2],
+    }
+  }
+
+  fn process(&mut self, input: f64) -> f64 {
+    let output = self.b[0] * input + self.state[0];
+    self.state[0] = self.b[1] * input - self.a[0] * output + self.state[1];
+    self.state[1] = self.b[2] * input - self.a[1] * output;
+    output
+  }
+}
+
+/// The K-weighting of ITU-R BS.1770, a high shelf modelling the head followed by a high
+/// pass, computed for any sample rate.
+fn k_weighting(sample_rate: u32) -> [Biquad; 2] {
+  let sample_rate = sample_rate as f64;
+
+  let k = (PI * 1681.974450955533 / sample_rate).tan();
+  let q = 0.7071752369554196;
+  let vh = 10f64.powf(3.999843853973347 / 20.0);
+  let vb = vh.powf(0.4996667741545416);
+  let a0 = 1.0 + k / q + k * k;
+  let shelf = Biquad::new(
+    [
+      (vh + vb * k / q + k * k) / a0,
+      2.0 * (k * k - vh) / a0,
+      (vh - vb * k / q + k * k) / a0,
+    ],
+    [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+  );
+
+  let k = (PI * 38.13547087602444 / sample_rate).tan();
+  let q = 0.5003270373238773;
+  let a0 = 1.0 + k / q + k * k;
+  let high_pass = Biquad::new(
+    [1.0, -2.0, 1.0],
+    [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+  );
+
+  [shelf, high_pass]
+}
+
+/// Finds peaks between the samples by oversampling with a windowed sinc interpolator.
+struct TruePeak {
+  /// Interpolation filter split into one set of taps per oversampled position
+  phases: Vec<[f32; TRUE_PEAK_TAPS]>,
+  history: VecDeque<f32>,
+}
+
+impl TruePeak {
+  fn new(sample_rate: u32) -> Self {
+    // High sample rates already catch most of the peaks
+    let factor = match sample_rate {
+      ..96000 => 4,
+      96000..192000 => 2,
+      _ => 1,
+    };
+
+    let len = factor * TRUE_PEAK_TAPS;
+    let center = (len - 1) as f32 / 2.0;
+    let phases = (0..factor)
+      .map(|phase| {
+        std::array::from_fn(|tap| {
+          let index = tap * factor + phase;
+          let x = (index as f32 - center) / factor as f32;
+          let sinc = if x == 0.0 {
+            1.0
+          } else {
+            (std::f32::consts::PI * x).sin() / (std::f32::consts::PI * x)
+          };
+          let hann = 0.5 - 0.5 * (std::f32::consts::TAU * (index as f32 + 0.5) / len as f32).cos();
+          sinc * hann
+        })
+      })
+      .collect();
+
+    Self {
+      phases,
+      history: VecDeque::from(vec![0.0; TRUE_PEAK_TAPS]),
+    }
+  }
+
+  /// Adds a sample and returns the highest absolute level around it.
+  fn process(&mut self, sample: f32) -> f32 {
+    self.history.pop_back();
+    self.history.push_front(sample);
+
+    self
+      .phases
+      .iter()
+      .map(|taps| {
+        taps
+          .iter()
+          .zip(&self.history)
+          .map(|(tap, sample)| tap * sample)
+          .sum::<f32>()
+          .abs()
+      })
+      .fold(sample.abs(), f32::max)
+  }
+}
+
+/// Sums of the samples of one block.
+#[derive(Debug, Clone, Copy, Default)]
+struct Block {
+  weighted_squares: f64,
+  squares: f64,
+  peak: f32,
+  len: usize,
+}
+
+impl Block {
+  /// Adds `share` of `other`, for blocks only partly inside a window.
+  fn add(&mut self, other: &Block, share: f64) {
+    self.weighted_squares += other.weighted_squares * share;
+    self.squares += other.squares * share;
+    self.peak = self.peak.max(other.peak);
+    self.len += (other.len as f64 * share).round() as usize;
+  }
+
+  fn weighted_energy(&self) -> f64 {
+    self.weighted_squares / self.len.max(1) as f64
+  }
+
+  fn rms(&self) -> f32 {
+    (self.squares / self.len.max(1) as f64).sqrt() as f32
+  }
+}
+
+/// Loudness distribution of the gating blocks, so integrated loudness can be measured over
+/// any length in constant memory.
+struct GatingHistogram {
+  counts: Vec<u32>,
+  energies: Vec<f64>,
+}
+
+impl GatingHistogram {
+  fn new() -> Self {
+    let len = ((MAX_LOUDNESS - MIN_LOUDNESS) / HISTOGRAM_STEP) as usize;
+    Self {
+      counts: vec![0; len],
+      energies: vec![0.0; len],
+    }
+  }
+
+  fn add(&mut self, energy: f64) {
+    let loudness = energy_to_loudness(energy);
+    // Absolute gate
+    if loudness <= MIN_LOUDNESS {
+      return;
+    }
+
+    let index = (((loudness - MIN_LOUDNESS) / HISTOGRAM_STEP) as usize).min(self.counts.len() - 1);
+    self.counts[index] += 1;
+    self.energies[index] += energy;
+  }
+
+  fn integrated(&self) -> f32 {
+    let mean_from = |start: usize| {
+      let count: u32 = self.counts[start..].iter().sum();
+      let energy: f64 = self.energies[start..].iter().sum();
+      (count > 0).then(|| energy / count as f64)
+    };
+
+    let Some(ungated) = mean_from(0) else {
+      return MIN_LOUDNESS;
+    };
+
+    let relative_gate = energy_to_loudness(ungated) + RELATIVE_GATE;
+    let start = ((relative_gate - MIN_LOUDNESS) / HISTOGRAM_STEP)
+      .ceil()
+      .max(0.0) as usize;
+    mean_from(start.min(self.counts.len() - 1)).map_or(MIN_LOUDNESS, energy_to_loudness)
+  }
+}
+
+struct ChannelMeter {
+  filters: [Biquad; 2],
+  true_peak: TruePeak,
+  current: Block,
+  /// The newest complete blocks
+  blocks: VecDeque<Block>,
+  histogram: GatingHistogram,
+}
+
+impl ChannelMeter {
+  fn new(sample_rate: u32) -> Self {
+    Self {
+      filters: k_weighting(sample_rate),
+      true_peak: TruePeak::new(sample_rate),
+      current: Block::default(),
+      blocks: VecDeque::with_capacity(SHORT_TERM_BLOCKS),
+      histogram: GatingHistogram::new(),
+    }
+  }
+
+  fn add_sample(&mut self, sample: f32) {
+    let weighted = self
+      .filters
+      .iter_mut()
+      .fold(sample as f64, |sample, filter| filter.process(sample));
+
+    self.current.weighted_squares += weighted * weighted;
+    self.current.squares += sample as f64 * sample as f64;
+    self.current.peak = self.current.peak.max(self.true_peak.process(sample));
+    self.current.len += 1;
+  }
+
+  /// Completes the current block, returning the energy of the newest gating block once
+  /// there are enough blocks.
+  fn finish_block(&mut self) -> Option<f64> {
+    if self.blocks.len() == SHORT_TERM_BLOCKS {
+      self.blocks.pop_front();
+    }
+    self.blocks.push_back(self.current);
+    self.current = Block::default();
+
+    // Gating blocks are a momentary window long and overlap by 75 %
+    if self.blocks.len() < MOMENTARY_BLOCKS {
+      return None;
+    }
+
+    let mut gating_block = Block::default();
+    for block in self.blocks.iter().rev().take(MOMENTARY_BLOCKS) {
+      gating_block.add(block, 1.0);
+    }
+
+    let energy = gating_block.weighted_energy();
+    self.histogram.add(energy);
+    Some(energy)
+  }
+
+  /// Sums the newest `block_count` blocks worth of samples, the window slides with every
+  /// sample instead of jumping from block to block.
+  fn window(&self, block_count: usize, block_len: usize) -> Block {
+    let mut window = self.current;
+    let mut blocks = self.blocks.iter().rev();
+    for block in blocks.by_ref().take(block_count - 1) {
+      window.add(block, 1.0);
+    }
+
+    // The oldest block only partly overlaps the window
+    if let Some(oldest) = blocks.next() {
+      window.add(oldest, 1.0 - self.current.len as f64 / block_len as f64);
+    }
+
+    window
+  }
+}
+
+/// Measures RMS, true peak and EBU R128 loudness of every channel of a stream.
+pub struct LoudnessMeter {
+  channels: Vec<ChannelMeter>,
+  block_len: usize,
+  program_histogram: GatingHistogram,
+}
+
+impl LoudnessMeter {
+  pub fn new(sample_rate: u32, channel_count: usize) -> Self {
+    Self {
+      channels: (0..channel_count)
+        .map(|_| ChannelMeter::new(sample_rate))
+        .collect(),
+      block_len: (sample_rate / BLOCKS_PER_SECOND).max(1) as usize,
+      program_histogram: GatingHistogram::new(),
+    }
+  }
+
+  /// Measures interleaved `samples`, every one of them has to be passed in exactly once.
+  pub fn process(&mut self, samples: &[f32]) {
+    if self.channels.is_empty() {
+      return;
+    }
+
+    for frame in samples.chunks_exact(self.channels.len()) {
+      for (channel, sample) in self.channels.iter_mut().zip(frame) {
+        channel.add_sample(*sample);
+      }
+
+      // Every channel completes its blocks at the same time
+      if self.channels[0].current.len == self.block_len {
+        let energies: Option<Vec<f64>> = self
+          .channels
+          .iter_mut()
+          .map(ChannelMeter::finish_block)
+          .collect();
+
+        // Channels are weighted equally, the surround weights of BS.1770 are ignored
+        if let Some(energies) = energies {
+          self.program_histogram.add(energies.iter().sum());
+        }
+      }
+    }
+  }
+
+  pub fn loudness(&self) -> Loudness {
+    let windows: Vec<(Block, Block)> = self
+      .channels
+      .iter()
+      .map(|channel| {
+        (
+          channel.window(MOMENTARY_BLOCKS, self.block_len),
+          channel.window(SHORT_TERM_BLOCKS, self.block_len),
+        )
+      })
+      .collect();
+
+    let channels = self
+      .channels
+      .iter()
+      .zip(&windows)
+      .map(|(channel, (momentary, short_term))| LoudnessLevels {
+        rms: momentary.rms(),
+        true_peak: momentary.peak,
+        momentary: energy_to_loudness(momentary.weighted_energy()),
+        short_term: energy_to_loudness(short_term.weighted_energy()),
+        integrated: channel.histogram.integrated(),
+      })
+      .collect::<Vec<_>>();
+
+    let channel_count = channels.len().max(1) as f32;
+    let program = LoudnessLevels {
+      rms: (channels
+        .iter()
+        .map(|levels| levels.rms.powi(2))
+        .sum::<f32>()
+        / channel_count)
+        .sqrt(),
+      true_peak: channels
+        .iter()
+        .map(|levels| levels.true_peak)
+        .fold(0.0, f32::max),
+      momentary: energy_to_loudness(
+        windows
+          .iter()
+          .map(|(momentary, _)| momentary.weighted_energy())
+          .sum(),
+      ),
+      short_term: energy_to_loudness(
+        windows
+          .iter()
+          .map(|(_, short_term)| short_term.weighted_energy())
+          .sum(),
+      ),
+      integrated: self.program_histogram.integrated(),
+    };
+
+    Loudness { channels, program }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::f64::consts::TAU;
+
+  use super::*;
+
+  /// Interleaved stereo sine with the same peak `level` in dBFS on both channels.
+  fn stereo_sine(
+    sample_rate: u32,
+    frequency: f64,
+    level: f64,
+    phase: f64,
+    seconds: f64,
+  ) -> Vec<f32> {
+    let amplitude = 10f64.powf(level / 20.0);
+    (0..(seconds * sample_rate as f64) as usize)
+      .flat_map(|index| {
+        let sample =
+          (amplitude * (TAU * frequency * index as f64 / sample_rate as f64 + phase).sin()) as f32;
+        [sample, sample]
+      })
+      .collect()
+  }
+
+  fn measure(sample_rate: u32, samples: &[f32]) -> Loudness {
+    let mut meter = LoudnessMeter::new(sample_rate, 2);
+    meter.process(samples);
+    meter.loudness()
+  }
+
+  fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+    assert!(
+      (actual - expected).abs() <= tolerance,
+      "expected {} ± {}, got {}",
+      expected,
+      tolerance,
+      actual
+    );
+  }
+
+  #[test]
+  fn reference_sine_reads_minus_23_lufs_per_channel() {
+    for sample_rate in [44100, 48000] {
+      let loudness = measure(
+        sample_rate,
+        &stereo_sine(sample_rate, 997.0, -20.0, 0.0, 10.0),
+      );
+
+      for levels in &loudness.channels {
+        assert_close(levels.momentary, -23.0, 0.1);
+        assert_close(levels.short_term, -23.0, 0.1);
+        assert_close(levels.integrated, -23.0, 0.1);
+      }
+      // Both channels add up
+      assert_close(loudness.program.integrated, -20.0, 0.1);
+    }
+  }
+
+  #[test]
+  fn k_weighting_follows_the_bs_1770_response() {
+    let reference = measure(48000, &stereo_sine(48000, 997.0, -20.0, 0.0, 5.0))
+      .program
+      .integrated;
+
+    // Response of the BS.1770 filters relative to 997 Hz
+    for (frequency, gain) in [(20.0, -13.97), (100.0, -1.82), (10000.0, 3.35)] {
+      let loudness = measure(48000, &stereo_sine(48000, frequency, -20.0, 0.0, 5.0));
+      assert_close(loudness.program.integrated - reference, gain, 0.1);
+    }
+  }
+
+  #[test]
+  fn relative_gate_excludes_quiet_sections() {
+    // -20 LUFS followed by as long a section at -40 LUFS, which would pull the ungated
+    // average down to -23 LUFS
+    let mut samples = stereo_sine(48000, 997.0, -20.0, 0.0, 20.0);
+    samples.extend(stereo_sine(48000, 997.0, -40.0, 0.0, 20.0));
+
+    assert_close(measure(48000, &samples).program.integrated, -20.0, 0.1);
+  }
+
+  #[test]
+  fn absolute_gate_excludes_silence() {
+    let mut samples = stereo_sine(48000, 997.0, -20.0, 0.0, 10.0);
+    samples.extend(vec![0.0; 48000 * 2 * 10]);
+
+    let loudness = measure(48000, &samples);
+    assert_close(loudness.program.integrated, -20.0, 0.1);
+    assert_eq!(loudness.program.momentary, MIN_LOUDNESS);
+  }
+
+  #[test]
+  fn true_peak_finds_peaks_between_samples() {
+    // A quarter of the sample rate shifted by an eighth of a period never samples its
+    // peak, every sample lands at 1/√2 of it
+    let samples = stereo_sine(48000, 12000.0, -6.0, TAU / 8.0, 1.0);
+    let sample_peak = samples
+      .iter()
+      .fold(0.0, |peak: f32, sample| peak.max(sample.abs()));
+    let true_peak = measure(48000, &samples).program.true_peak;
+
+    assert_close(sample_peak, 10f32.powf(-6.0 / 20.0) / 2f32.sqrt(), 0.001);
+    assert_close(20.0 * true_peak.log10(), -6.0, 0.2);
+  }
+}
@@ -0,0 +1,157 @@
+use std::{
+  collections::VecDeque,
+  time::{Duration, Instant},
+};
+
+/// Flux of this long a history makes up the adaptive threshold
+const HISTORY: Duration = Duration::from_millis(1500);
+/// Onsets closer together than this are counted once
+const MIN_ONSET_INTERVAL: Duration = Duration::from_millis(100);
+/// Flux below this is never an onset, so noise in near silence does not trigger beats
+const MIN_FLUX: f32 = 0.002;
+/// Flux has to be at least this many times its recent mean, so steady noise with a small
+/// deviation does not trigger beats
+const MIN_FLUX_RATIO: f32 = 2.0;
+/// Fewer analyzed frames than this don't make a meaningful threshold yet
+const MIN_HISTORY_LEN: usize = 8;
+/// Time constant of the beat pulse
+const PULSE_DECAY: Duration = Duration::from_millis(150);
+
+#[derive(Debug, Clone)]
+pub struct OnsetConfig {
+  /// Standard deviations the flux has to rise above its recent mean to count as an onset
+  pub sensitivity: f32,
+}
+
+impl Default for OnsetConfig {
+  fn default() -> Self {
+    Self { sensitivity: 1.5 }
+  }
+}
+
+/// Beats detected so far, as seen at the time of the last analysis.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Beat {
+  pub count: u32,
+  /// Seconds since the last beat
+  pub time_since: f32,
+  /// 1 on a beat, decaying towards 0 afterwards
+  pub pulse: f32,
+}
+
+/// Spectral flux onset detection with a threshold that adapts to the recent flux.
+pub struct OnsetDetector {
+  config: OnsetConfig,
+  previous: Vec<Vec<f32>>,
+  history: VecDeque<(Instant, f32)>,
+  /// Flux of the newest spectrum
+  flux: f32,
+  count: u32,
+  last_onset: Option<Instant>,
+}
+
+impl OnsetDetector {
+  pub fn new(config: OnsetConfig) -> Self {
+    Self {
+      config,
+      previous: Vec::new(),
+      history: VecDeque::new(),
+      flux: 0.0,
+      count: 0,
+      last_onset: None,
+    }
+  }
+
+  /// Sum of the increases of every bin of every channel since the last spectrum, on a
+  /// logarithmic scale so quiet and loud parts weigh alike, averaged over the bins.
+  fn spectral_flux(&self, spectrum: &[Vec<f32>]) -> Option<f32> {
+    let same_shape = self.previous.len() == spectrum.len()
+      && self
+        .previous
+        .iter()
+        .zip(spectrum)
+        .all(|(previous, channel)| previous.len() == channel.len());
+    if !same_shape {
+      return None;
+    }
+
+    let mut flux = 0.0;
+    let mut bin_count = 0;
+    for (previous, channel) in self.previous.iter().zip(spectrum) {
+      for (previous, current) in previous.iter().zip(channel) {
+        flux += (current.ln_1p() - previous.ln_1p()).max(0.0);
+      }
+      bin_count += channel.len();
+    }
+
+    Some(flux / bin_count.max(1) as f32)
+  }
+
+  /// Looks for an onset in the newest raw, unsmoothed `spectrum` and reports the beats.
+  pub fn process(&mut self, spectrum: &[Vec<f32>], now: Instant) -> Beat {
+    let flux = self.spectral_flux(spectrum);
+    self.previous = spectrum.to_vec();
+    self.flux = flux.unwrap_or(0.0);
+
+    if let Some(flux) = flux {
+      while self
+        .history
+        .front()
+        .is_some_and(|(time, _)| now.duration_since(*time) > HISTORY)
+      {
+        self.history.pop_front();
+      }
+
+      if self.is_onset(flux, now) {
+        self.count = self.count.wrapping_add(1);
+        self.last_onset = Some(now);
+      }
+
+      self.history.push_back((now, flux));
+    }
+
+    self.beat(now)
+  }
+
+  /// Onset strength of the newest spectrum.
+  pub fn flux(&self) -> f32 {
+    self.flux
+  }
+
+  fn is_onset(&self, flux: f32, now: Instant) -> bool {
+    if flux < MIN_FLUX || self.history.len() < MIN_HISTORY_LEN {
+      return false;
+    }
+
+    let too_soon = self
+      .last_onset
+      .is_some_and(|last_onset| now.duration_since(last_onset) < MIN_ONSET_INTERVAL);
+    if too_soon {
+      return false;
+    }
+
+    let len = self.history.len() as f32;
+    let mean = self.history.iter().map(|(_, flux)| flux).sum::<f32>() / len;
+    let variance = self
+      .history
+      .iter()
+      .map(|(_, flux)| (flux - mean).powi(2))
+      .sum::<f32>()
+      / len;
+
+    flux > mean + self.config.sensitivity * variance.sqrt() && flux > mean * MIN_FLUX_RATIO
+  }
+
+  fn beat(&self, now: Instant) -> Beat {
+    let Some(last_onset) = self.last_onset else {
+      return Beat::default();
+    };
+
+    let time_since = now.duration_since(last_onset).as_secs_f32();
+    Beat {
+      count: self.count,
+      time_since,
+      pulse: (-time_since / PULSE_DECAY.as_secs_f32()).exp(),
+    }
+  }
+}
@@ -0,0 +1,230 @@
+use std::{
+  fs::File,
+  io::{self, Read},
+  path::PathBuf,
+  str::FromStr,
+  thread,
+};
+
+use super::{
+  AudioSource,
+  ring_buffer::{self, Consumer, Producer},
+};
+
+/// Length of audio buffered between the reader thread and the analyzer
+const RING_BUFFER_SECONDS: usize = 1;
+
+/// Sample encodings accepted for raw PCM input, named like `sox`, `parec` and `aplay` do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcmFormat {
+  U8,
+  S8,
+  S16Le,
+  S16Be,
+  S24Le,
+  S24Be,
+  S32Le,
+  S32Be,
+  F32Le,
+  F32Be,
+}
+
+impl PcmFormat {
+  fn bytes_per_sample(self) -> usize {
+    match self {
+      Self::U8 | Self::S8 => 1,
+      Self::S16Le | Self::S16Be => 2,
+      Self::S24Le | Self::S24Be => 3,
+      Self::S32Le | Self::S32Be | Self::F32Le | Self::F32Be => 4,
+    }
+  }
+
+  fn decode(self, bytes: &[u8]) -> f32 {
+    match self {
+      Self::U8 => (bytes[0] as f32 - 128.0) / 128.0,
+      Self::S8 => bytes[0] as i8 as f32 / 128.0,
+      Self::S16Le => i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+      Self::S16Be => i16::from_be_bytes([bytes[0], bytes[1]]) as f32 / 32768.0,
+      Self::S24Le => i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) as f32 / 2147483648.0,
+      Self::S24Be => i32::from_be_bytes([bytes[0], bytes[1], bytes[2], 0]) as f32 / 2147483648.0,
+      Self::S32Le => {
+        i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32 / 2147483648.0
+      }
+      Self::S32Be => {
+        i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f32 / 2147483648.0
+      }
+      Self::F32Le => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+      Self::F32Be => f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+    }
+  }
+}
+
+impl FromStr for PcmFormat {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    Ok(match value.to_lowercase().as_str() {
+      "u8" => Self::U8,
+      "s8" => Self::S8,
+      "s16" | "s16le" => Self::S16Le,
+      "s16be" => Self::S16Be,
+      "s24" | "s24le" => Self::S24Le,
+      "s24be" => Self::S24Be,
+      "s32" | "s32le" => Self::S32Le,
+      "s32be" => Self::S32Be,
+      "f32" | "f32le" | "float32le" => Self::F32Le,
+      "f32be" | "float32be" => Self::F32Be,
+      _ => {
+        return Err(format!(
+          "unknown PCM format `{}`, expected one of u8, s8, s16le, s16be, s24le, s24be, s32le, \
+           s32be, f32le or f32be",
+          value
+        ));
+      }
+    })
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct PcmConfig {
+  /// File or named pipe to read from, stdin when `None`
+  pub path: Option<PathBuf>,
+  pub format: PcmFormat,
+  pub sample_rate: u32,
+  pub channel_count: usize,
+}
+
+impl Default for PcmConfig {
+  /// Matches MPD's default FIFO output format.
+  fn default() -> Self {
+    Self {
+      path: None,
+      format: PcmFormat::S16Le,
+      sample_rate: 44100,
+      channel_count: 2,
+    }
+  }
+}
+
+/// Raw interleaved PCM read from stdin, a file or a named pipe.
+///
+/// Reading happens on a background thread since opening and reading a pipe blocks
+/// until a writer shows up. Named pipes are reopened when the writer goes away, so
+/// restarting the producer (like MPD) does not end the visualization.
+pub struct PcmSource {
+  consumer: Consumer,
+  sample_rate: u32,
+  channel_count: usize,
+}
+
+impl PcmSource {
+  pub fn open(config: &PcmConfig) -> Self {
+    let (producer, consumer) = ring_buffer::ring_buffer(
+      config.sample_rate as usize * config.channel_count * RING_BUFFER_SECONDS,
+    );
+
+    let reader_config = config.clone();
+    thread::Builder::new()
+      .name("pcm reader".to_string())
+      .spawn(move || read_pcm(reader_config, producer))
+      .expect("failed to spawn the PCM reader thread");
+
+    Self {
+      consumer,
+      sample_rate: config.sample_rate,
+      channel_count: config.channel_count,
+    }
+  }
+}
+
+impl AudioSource for PcmSource {
+  fn sample_rate(&self) -> u32 {
+    self.sample_rate
+  }
+
+  fn channel_count(&self) -> usize {
+    self.channel_count
+  }
+
+  fn read_frames(&mut self, buffer: &mut Vec<f32>, max_frames: usize) -> usize {
+    self
+      .consumer
+      .pop_latest(buffer, max_frames * self.channel_count)
+      / self.channel_count
+  }
+}
+
+fn read_pcm(config: PcmConfig, mut producer: Producer) {
+  loop {
+    let result = match &config.path {
+      Some(path) => File::open(path).and_then(|file| forward_samples(file, &config, &mut producer)),
+      None => forward_samples(io::stdin().lock(), &config, &mut producer),
+    };
+
+    match result {
+      Ok(()) => log::info!("PCM input reached the end of the stream"),
+      Err(error) => log::error!("failed to read PCM input: {}", error),
+    }
+
+    // Only a path can be reopened, and only when the analyzer is still listening
+    let is_pipe = config
+      .path
+      .as_ref()
+      .and_then(|path| path.metadata().ok())
+      .is_some_and(|metadata| is_fifo(&metadata));
+
+    if !is_pipe || producer.is_abandoned() {
+      return;
+    }
+  }
+}
+
+/// Reads `reader` until it ends, writing whole frames as normalized f32.
+fn forward_samples(
+  mut reader: impl Read,
+  config: &PcmConfig,
+  producer: &mut Producer,
+) -> io::Result<()> {
+  let bytes_per_sample = config.format.bytes_per_sample();
+  let bytes_per_frame = bytes_per_sample * config.channel_count;
+
+  let mut bytes = vec![0; bytes_per_frame * 1024];
+  // Bytes of an incomplete frame left over from the previous read
+  let mut pending = 0;
+
+  loop {
+    let read = match reader.read(&mut bytes[pending..]) {
+      Ok(0) => return Ok(()),
+      Ok(read) => read,
+      Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+      Err(error) => return Err(error),
+    };
+
+    let available = pending + read;
+    let complete = available - available % bytes_per_frame;
+
+    if producer.is_abandoned() {
+      return Ok(());
+    }
+
+    // Frames that don't fit are dropped, the analyzer only wants the newest ones anyway
+    let samples = bytes[..complete]
+      .chunks_exact(bytes_per_sample)
+      .map(|sample| config.format.decode(sample));
+    producer.push(complete / bytes_per_sample, samples);
+
+    bytes.copy_within(complete..available, 0);
+    pending = available - complete;
+  }
+}
+
+#[cfg(unix)]
+fn is_fifo(metadata: &std::fs::Metadata) -> bool {
+  use std::os::unix::fs::FileTypeExt;
+  metadata.file_type().is_fifo()
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_metadata: &std::fs::Metadata) -> bool {
+  false
+}
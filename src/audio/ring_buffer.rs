@@ -0,0 +1,175 @@
+use std::sync::{
+  Arc,
+  atomic::{AtomicU32, AtomicUsize, Ordering},
+};
+
+/// Samples are stored as their bit patterns, so the buffer can be shared without locks
+/// or unsafe code.
+struct Shared {
+  samples: Box<[AtomicU32]>,
+  /// Total number of samples ever written, only advanced by the producer
+  write_index: AtomicUsize,
+  /// Total number of samples ever read, only advanced by the consumer
+  read_index: AtomicUsize,
+}
+
+/// Creates a preallocated single-producer/single-consumer ring buffer of f32 samples.
+pub fn ring_buffer(capacity: usize) -> (Producer, Consumer) {
+  let shared = Arc::new(Shared {
+    samples: (0..capacity.max(1)).map(|_| AtomicU32::new(0)).collect(),
+    write_index: AtomicUsize::new(0),
+    read_index: AtomicUsize::new(0),
+  });
+
+  (
+    Producer {
+      shared: shared.clone(),
+    },
+    Consumer { shared },
+  )
+}
+
+/// Writing end of the ring buffer, safe to use on a real-time audio thread since it
+/// never allocates, locks or blocks.
+pub struct Producer {
+  shared: Arc<Shared>,
+}
+
+impl Producer {
+  /// Writes `len` samples taken from `samples`, or none of them when they don't fit.
+  ///
+  /// Writing all or nothing keeps interleaved frames intact when the consumer falls behind.
+  pub fn push(&mut self, len: usize, samples: impl IntoIterator<Item = f32>) -> bool {
+    let capacity = self.shared.samples.len();
+    let write_index = self.shared.write_index.load(Ordering::Relaxed);
+    let read_index = self.shared.read_index.load(Ordering::Acquire);
+
+    if len > capacity - (write_index - read_index) {
+      return false;
+    }
+
+    for (offset, sample) in samples.into_iter().take(len).enumerate() {
+      self.shared.samples[(write_index + offset) % capacity]
+        .store(sample.to_bits(), Ordering::Relaxed);
+    }
+
+    self
+      .shared
+      .write_index
+      .store(write_index + len, Ordering::Release);
+    true
+  }
+
+  /// Whether the consumer was dropped, so nobody will read what is written anymore.
+  pub fn is_abandoned(&self) -> bool {
+    Arc::strong_count(&self.shared) == 1
+  }
+}
+
+/// Reading end of the ring buffer.
+pub struct Consumer {
+  shared: Arc<Shared>,
+}
+
+impl Consumer {
+  /// Appends the newest pending samples, at most `max_len` of them, to `buffer`.
+  /// Everything older is dropped, so a slow reader never builds up latency.
+  pub fn pop_latest(&mut self, buffer: &mut Vec<f32>, max_len: usize) -> usize {
+    let capacity = self.shared.samples.len();
+    let write_index = self.shared.write_index.load(Ordering::Acquire);
+    let read_index = self.shared.read_index.load(Ordering::Relaxed);
+
+    let available = write_index - read_index;
+    let len = available.min(max_len);
+
+    buffer
+      .extend((write_index - len..write_index).map(|index| {
+        f32::from_bits(self.shared.samples[index % capacity].load(Ordering::Relaxed))
+      }));
+
+    self.shared.read_index.store(write_index, Ordering::Release);
+    len
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::thread;
+
+  use super::*;
+
+  fn pop_all(consumer: &mut Consumer) -> Vec<f32> {
+    let mut buffer = Vec::new();
+    consumer.pop_latest(&mut buffer, usize::MAX);
+    buffer
+  }
+
+  #[test]
+  fn wraps_around_the_end_of_the_buffer() {
+    let (mut producer, mut consumer) = ring_buffer(4);
+
+    for round in 0..5 {
+      let samples = [0.0, 1.0, 2.0].map(|sample| sample + round as f32 * 3.0);
+      assert!(producer.push(samples.len(), samples));
+      assert_eq!(pop_all(&mut consumer), samples);
+    }
+  }
+
+  #[test]
+  fn rejects_pushes_that_do_not_fit() {
+    let (mut producer, mut consumer) = ring_buffer(4);
+
+    assert!(producer.push(3, [1.0, 2.0, 3.0]));
+    assert!(!producer.push(2, [4.0, 5.0]));
+    assert!(producer.push(1, [6.0]));
+    assert!(!producer.push(1, [7.0]));
+
+    assert_eq!(pop_all(&mut consumer), [1.0, 2.0, 3.0, 6.0]);
+  }
+
+  #[test]
+  fn pop_latest_drops_older_samples() {
+    let (mut producer, mut consumer) = ring_buffer(8);
+    assert!(producer.push(5, [1.0, 2.0, 3.0, 4.0, 5.0]));
+
+    let mut buffer = Vec::new();
+    assert_eq!(consumer.pop_latest(&mut buffer, 2), 2);
+    assert_eq!(buffer, [4.0, 5.0]);
+
+    // The dropped samples are gone for good
+    assert_eq!(pop_all(&mut consumer), []);
+  }
+
+  #[test]
+  fn transfers_samples_between_threads_in_order() {
+    const FRAME_COUNT: usize = 200_000;
+    let (mut producer, mut consumer) = ring_buffer(64);
+
+    // Every frame holds two consecutive numbers, which stay exact as f32
+    let writer = thread::spawn(move || {
+      for frame in 0..FRAME_COUNT {
+        let samples = [(frame * 2) as f32, (frame * 2 + 1) as f32];
+        while !producer.push(2, samples) {
+          thread::yield_now();
+        }
+      }
+    });
+
+    let mut expected = 0.0;
+    let mut buffer = Vec::new();
+    while expected < (FRAME_COUNT * 2) as f32 {
+      buffer.clear();
+      if consumer.pop_latest(&mut buffer, 64) == 0 {
+        thread::yield_now();
+        continue;
+      }
+
+      for sample in &buffer {
+        assert_eq!(*sample, expected, "samples were torn, lost or reordered");
+        expected += 1.0;
+      }
+    }
+
+    writer.join().unwrap();
+  }
+}
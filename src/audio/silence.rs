@@ -0,0 +1,85 @@
+use std::time::{Duration, Instant};
+
+/// Time to fade back in once audio returns
+const FADE_IN: Duration = Duration::from_millis(500);
+/// Time to fade out into the idle animation
+const FADE_OUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone)]
+pub struct SilenceConfig {
+  /// Peak level in dBFS below which the input counts as silent
+  pub threshold: f32,
+  /// How long the input has to stay silent before switching to idle
+  pub hold: Duration,
+}
+
+impl Default for SilenceConfig {
+  fn default() -> Self {
+    Self {
+      threshold: -60.0,
+      hold: Duration::from_secs(5),
+    }
+  }
+}
+
+/// Switches to idle after a stretch of silence and fades between idle and active.
+pub struct SilenceDetector {
+  config: SilenceConfig,
+  last_sound: Instant,
+  /// 1 while active, 0 when idle, in between while fading
+  activity: f32,
+  last_update: Option<Instant>,
+}
+
+impl SilenceDetector {
+  pub fn new(config: SilenceConfig) -> Self {
+    Self {
+      config,
+      last_sound: Instant::now(),
+      activity: 1.0,
+      last_update: None,
+    }
+  }
+
+  /// Checks newly read interleaved `samples`, no samples at all count as silence.
+  pub fn process(&mut self, samples: &[f32], now: Instant) {
+    let was_idle = self.is_idle();
+    let elapsed = self
+      .last_update
+      .map_or(Duration::ZERO, |last_update| now - last_update);
+    self.last_update = Some(now);
+
+    let threshold = 10f32.powf(self.config.threshold / 20.0);
+    if samples.iter().any(|sample| sample.abs() > threshold) {
+      self.last_sound = now;
+    }
+
+    let is_idle = self.is_idle_at(now);
+    if is_idle && !was_idle {
+      log::info!("input silent, idling");
+    } else if was_idle && !is_idle {
+      log::info!("audio returned");
+    }
+
+    // Linear fades end exactly at 0, so a renderer can tell when it is fully idle
+    self.activity = if is_idle {
+      (self.activity - elapsed.as_secs_f32() / FADE_OUT.as_secs_f32()).max(0.0)
+    } else {
+      (self.activity + elapsed.as_secs_f32() / FADE_IN.as_secs_f32()).min(1.0)
+    };
+  }
+
+  fn is_idle_at(&self, time: Instant) -> bool {
+    time.saturating_duration_since(self.last_sound) >= self.config.hold
+  }
+
+  pub fn is_idle(&self) -> bool {
+    self
+      .last_update
+      .is_some_and(|last_update| self.is_idle_at(last_update))
+  }
+
+  pub fn activity(&self) -> f32 {
+    self.activity
+  }
+}
@@ -0,0 +1,116 @@
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone)]
+pub struct SmoothingConfig {
+  /// Time constant for rising values
+  pub attack: Duration,
+  /// Time constant for falling values
+  pub release: Duration,
+  /// How long a peak stays before it starts falling
+  pub peak_hold: Duration,
+  /// Time constant of a falling peak
+  pub peak_decay: Duration,
+}
+
+impl Default for SmoothingConfig {
+  fn default() -> Self {
+    Self {
+      attack: Duration::from_millis(10),
+      release: Duration::from_millis(150),
+      peak_hold: Duration::from_millis(500),
+      peak_decay: Duration::from_millis(500),
+    }
+  }
+}
+
+/// Fraction of the remaining distance an exponential filter with time constant `tau` covers
+/// in `elapsed`, which is independent of how often the filter is updated.
+pub(super) fn smoothing_factor(elapsed: f32, tau: Duration) -> f32 {
+  let tau = tau.as_secs_f32();
+  if tau <= 0.0 {
+    return 1.0;
+  }
+
+  1.0 - (-elapsed / tau).exp()
+}
+
+/// Per bin attack/release smoothing and peak hold of every channel's spectrum.
+pub struct SpectrumSmoother {
+  config: SmoothingConfig,
+  smoothed: Vec<Vec<f32>>,
+  peaks: Vec<Vec<f32>>,
+  /// Seconds since every peak was last pushed up
+  peak_ages: Vec<Vec<f32>>,
+  last_update: Option<Instant>,
+}
+
+impl SpectrumSmoother {
+  pub fn new(config: SmoothingConfig) -> Self {
+    Self {
+      config,
+      smoothed: Vec::new(),
+      peaks: Vec::new(),
+      peak_ages: Vec::new(),
+      last_update: None,
+    }
+  }
+
+  /// Smooths `spectrum` in place and returns the held peaks of every bin.
+  ///
+  /// Starts over from the current values when the shape of the spectrum changed.
+  pub fn apply(&mut self, spectrum: &mut [Vec<f32>], now: Instant) -> Vec<Vec<f32>> {
+    let elapsed = self
+      .last_update
+      .map_or(0.0, |last_update| (now - last_update).as_secs_f32());
+    self.last_update = Some(now);
+
+    let same_shape = self.smoothed.len() == spectrum.len()
+      && self
+        .smoothed
+        .iter()
+        .zip(spectrum.iter())
+        .all(|(smoothed, channel)| smoothed.len() == channel.len());
+    if !same_shape {
+      self.smoothed = spectrum.to_vec();
+      self.peaks = spectrum.to_vec();
+      self.peak_ages = spectrum
+        .iter()
+        .map(|channel| vec![0.0; channel.len()])
+        .collect();
+      return self.peaks.clone();
+    }
+
+    let attack = smoothing_factor(elapsed, self.config.attack);
+    let release = smoothing_factor(elapsed, self.config.release);
+    let peak_decay = smoothing_factor(elapsed, self.config.peak_decay);
+    let peak_hold = self.config.peak_hold.as_secs_f32();
+
+    for (channel_index, channel) in spectrum.iter_mut().enumerate() {
+      let smoothed = &mut self.smoothed[channel_index];
+      let peaks = &mut self.peaks[channel_index];
+      let peak_ages = &mut self.peak_ages[channel_index];
+
+      for (bin, value) in channel.iter_mut().enumerate() {
+        let factor = if *value > smoothed[bin] {
+          attack
+        } else {
+          release
+        };
+        smoothed[bin] += (*value - smoothed[bin]) * factor;
+        *value = smoothed[bin];
+
+        if *value >= peaks[bin] {
+          peaks[bin] = *value;
+          peak_ages[bin] = 0.0;
+        } else {
+          peak_ages[bin] += elapsed;
+          if peak_ages[bin] > peak_hold {
+            peaks[bin] += (*value - peaks[bin]) * peak_decay;
+          }
+        }
+      }
+    }
+
+    self.peaks.clone()
+  }
+}
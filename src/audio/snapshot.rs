@@ -0,0 +1,298 @@
+use std::{
+  cell::UnsafeCell,
+  sync::{
+    Arc,
+    atomic::{AtomicUsize, Ordering},
+  },
+  time::Instant,
+};
+
+use super::{AudioFrame, BandEnergies};
+
+/// Set on the back buffer index while it holds a snapshot the reader has not taken yet
+const FRESH: usize = 0b100;
+const INDEX_MASK: usize = 0b11;
+
+/// An analyzed frame together with the time of its hop on the sample clock, see
+/// [`AudioProcessor::frame_time`](super::AudioProcessor::frame_time).
+pub struct Snapshot {
+  pub time: Instant,
+  pub frame: AudioFrame,
+}
+
+/// Three buffers, of which the writer owns one, the reader owns one and the third is the
+/// back buffer they swap theirs with.
+struct Shared {
+  buffers: [UnsafeCell<Option<Snapshot>>; 3],
+  /// Index of the back buffer, together with the `FRESH` bit
+  back: AtomicUsize,
+}
+
+// Every buffer is only ever accessed by the side that swapped it out of `back`, and the
+// swaps order the accesses of both sides
+unsafe impl Sync for Shared {}
+
+/// Creates a triple buffer that hands the newest snapshot from the analysis thread to the
+/// renderer.
+///
+/// It is lock-free and wait-free: publishing and taking are one atomic swap each, so
+/// neither side ever waits on or fails because of the other. The reader gets the newest
+/// snapshot published since its last take, older ones it never took are dropped.
+pub fn snapshot_buffer() -> (SnapshotWriter, SnapshotReader) {
+  let shared = Arc::new(Shared {
+    buffers: std::array::from_fn(|_| UnsafeCell::new(None)),
+    back: AtomicUsize::new(1),
+  });
+
+  (
+    SnapshotWriter {
+      shared: shared.clone(),
+      index: 0,
+    },
+    SnapshotReader { shared, index: 2 },
+  )
+}
+
+/// Writing end of the snapshot buffer.
+pub struct SnapshotWriter {
+  shared: Arc<Shared>,
+  /// Buffer owned by the writer
+  index: usize,
+}
+
+impl SnapshotWriter {
+  /// Replaces the newest snapshot.
+  pub fn publish(&mut self, snapshot: Snapshot) {
+    // SAFETY: the buffer at `index` was swapped out of `back`, so the reader can't access it
+    unsafe { *self.shared.buffers[self.index].get() = Some(snapshot) };
+
+    let back = self.shared.back.swap(self.index | FRESH, Ordering::AcqRel);
+    self.index = back & INDEX_MASK;
+  }
+}
+
+/// Reading end of the snapshot buffer.
+pub struct SnapshotReader {
+  shared: Arc<Shared>,
+  /// Buffer owned by the reader
+  index: usize,
+}
+
+impl SnapshotReader {
+  /// Takes the newest snapshot, if one was published since the last call.
+  pub fn take(&mut self) -> Option<Snapshot> {
+    // Only the writer sets the bit, so it can't be cleared between the load and the swap
+    if self.shared.back.load(Ordering::Relaxed) & FRESH == 0 {
+      return None;
+    }
+
+    let back = self.shared.back.swap(self.index, Ordering::AcqRel);
+    self.index = back & INDEX_MASK;
+    // SAFETY: the buffer at `index` was swapped out of `back`, so the writer can't access it
+    unsafe { (*self.shared.buffers[self.index].get()).take() }
+  }
+}
+
+/// Blends the two newest snapshots, so the visuals move smoothly even when the frame rate
+/// and the analysis rate differ.
+///
+/// Runs one snapshot interval behind the analysis, so there is always a newer snapshot to
+/// move towards. Spectra, peaks, waveforms and band energies are interpolated, everything
+/// else comes from the newer snapshot.
+///
+/// The interval between two snapshots comes from their sample clock times, the progress
+/// through it from the time the newer one was received, since the sample clock runs
+/// behind the wall clock by however long the audio was buffered.
+#[derive(Default)]
+pub struct SnapshotInterpolator {
+  previous: Option<Snapshot>,
+  current: Option<Snapshot>,
+  /// When `current` was pushed
+  received: Option<Instant>,
+}
+
+impl SnapshotInterpolator {
+  /// Adds a snapshot received at `now`, on the same clock as [`Self::frame`].
+  pub fn push(&mut self, snapshot: Snapshot, now: Instant) {
+    if self
+      .current
+      .as_ref()
+      .is_some_and(|current| snapshot.time <= current.time)
+    {
+      return;
+    }
+
+    self.previous = self.current.replace(snapshot);
+    self.received = Some(now);
+  }
+
+  /// Forgets every snapshot, so nothing is shown until new ones arrive.
+  pub fn clear(&mut self) {
+    self.previous = None;
+    self.current = None;
+    self.received = None;
+  }
+
+  pub fn frame(&self, now: Instant) -> Option<AudioFrame> {
+    let current = self.current.as_ref()?;
+    let Some(previous) = &self.previous else {
+      return Some(current.frame.clone());
+    };
+
+    let interval = (current.time - previous.time).as_secs_f32();
+    let elapsed = self.received.map_or(0.0, |received| {
+      now.saturating_duration_since(received).as_secs_f32()
+    });
+    let progress = if interval > 0.0 {
+      (elapsed / interval).min(1.0)
+    } else {
+      1.0
+    };
+
+    Some(interpolate(&previous.frame, &current.frame, progress))
+  }
+}
+
+fn interpolate(from: &AudioFrame, to: &AudioFrame, progress: f32) -> AudioFrame {
+  let lerp = |from: f32, to: f32| from + (to - from) * progress;
+  let lerp_channels = |from: &Vec<Vec<f32>>, to: &Vec<Vec<f32>>| {
+    // A changed layout or channel count can't be blended
+    let same_shape =
+      from.len() == to.len() && from.iter().zip(to).all(|(from, to)| from.len() == to.len());
+    if !same_shape {
+      return to.clone();
+    }
+
+    from
+      .iter()
+      .zip(to)
+      .map(|(from, to)| {
+        from
+          .iter()
+          .zip(to)
+          .map(|(from, to)| lerp(*from, *to))
+          .collect()
+      })
+      .collect()
+  };
+
+  let lerp_bands = |from: &BandEnergies, to: &BandEnergies| {
+    BandEnergies(std::array::from_fn(|band| lerp(from.0[band], to.0[band])))
+  };
+
+  AudioFrame {
+    spectrum: lerp_channels(&from.spectrum, &to.spectrum),
+    peaks: lerp_channels(&from.peaks, &to.peaks),
+    waveform: lerp_channels(&from.waveform, &to.waveform),
+    band_energies: lerp_bands(&from.band_energies, &to.band_energies),
+    channel_band_energies: if from.channel_band_energies.len() == to.channel_band_energies.len() {
+      from
+        .channel_band_energies
+        .iter()
+        .zip(&to.channel_band_energies)
+        .map(|(from, to)| lerp_bands(from, to))
+        .collect()
+    } else {
+      to.channel_band_energies.clone()
+    },
+    stereo: to.stereo,
+    beat: to.beat,
+    tempo: to.tempo,
+    loudness: to.loudness.clone(),
+    harmony: to.harmony,
+    descriptors: to.descriptors,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::{thread, time::Duration};
+
+  use super::*;
+
+  /// Snapshot numbered by `index`, which is stored in its spectrum and time.
+  fn snapshot(start: Instant, index: usize) -> Snapshot {
+    Snapshot {
+      time: start + Duration::from_millis(index as u64),
+      frame: AudioFrame {
+        spectrum: vec![vec![index as f32]],
+        ..Default::default()
+      },
+    }
+  }
+
+  fn index(snapshot: &Snapshot) -> usize {
+    snapshot.frame.spectrum[0][0] as usize
+  }
+
+  #[test]
+  fn takes_each_snapshot_once() {
+    let start = Instant::now();
+    let (mut writer, mut reader) = snapshot_buffer();
+    assert!(reader.take().is_none());
+
+    for expected in 0..5 {
+      writer.publish(snapshot(start, expected));
+      assert_eq!(reader.take().as_ref().map(index), Some(expected));
+      assert!(reader.take().is_none());
+    }
+  }
+
+  #[test]
+  fn take_skips_to_the_newest_snapshot() {
+    let start = Instant::now();
+    let (mut writer, mut reader) = snapshot_buffer();
+
+    for index in 0..5 {
+      writer.publish(snapshot(start, index));
+    }
+    assert_eq!(reader.take().as_ref().map(index), Some(4));
+    assert!(reader.take().is_none());
+  }
+
+  #[test]
+  fn interpolates_from_when_the_newest_snapshot_arrived() {
+    // The sample clock runs a second behind the wall clock
+    let start = Instant::now();
+    let received = start + Duration::from_secs(1);
+    let mut interpolator = SnapshotInterpolator::default();
+    interpolator.push(snapshot(start, 0), received);
+    interpolator.push(snapshot(start, 10), received);
+
+    let spectrum = |elapsed: u64| {
+      let frame = interpolator.frame(received + Duration::from_millis(elapsed));
+      frame.unwrap().spectrum[0][0]
+    };
+    assert_eq!(spectrum(0), 0.0);
+    assert_eq!(spectrum(5), 5.0);
+    assert_eq!(spectrum(20), 10.0);
+  }
+
+  #[test]
+  fn hands_snapshots_between_threads_in_order() {
+    const SNAPSHOT_COUNT: usize = 100_000;
+    let start = Instant::now();
+    let (mut writer, mut reader) = snapshot_buffer();
+
+    let writer_thread = thread::spawn(move || {
+      for index in 0..SNAPSHOT_COUNT {
+        writer.publish(snapshot(start, index));
+      }
+    });
+
+    let mut last = None;
+    while last != Some(SNAPSHOT_COUNT - 1) {
+      match reader.take() {
+        Some(snapshot) => {
+          let index = index(&snapshot);
+          assert!(last.is_none_or(|last| index > last));
+          assert_eq!(snapshot.time, start + Duration::from_millis(index as u64));
+          last = Some(index);
+        }
+        None => thread::yield_now(),
+      }
+    }
+
+    writer_thread.join().unwrap();
+  }
+}
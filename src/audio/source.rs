@@ -0,0 +1,43 @@
+/// Transport controls for sources that play back recorded audio.
+#[derive(Debug, Clone, Copy)]
+pub enum PlaybackCommand {
+  TogglePause,
+  /// Seeks relative to the current position, backwards when negative
+  SeekBy(f32),
+  CycleLoopMode,
+  NextTrack,
+  PreviousTrack,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceStatus {
+  Connected,
+  /// The source lost its input, for example because the device was unplugged
+  Disconnected,
+}
+
+/// A stream of interleaved audio frames that `AudioProcessor` can analyze.
+///
+/// Implementations deliver samples as normalized f32, with one sample per channel
+/// for every frame.
+pub trait AudioSource {
+  /// May change between reads, for example when a source reconnects to another device.
+  fn sample_rate(&self) -> u32;
+
+  /// May change between reads, for example when a source reconnects to another device.
+  fn channel_count(&self) -> usize;
+
+  /// Appends the interleaved frames that became available since the last call to
+  /// `buffer` and returns the number of frames that were appended.
+  ///
+  /// Only the newest `max_frames` frames are appended, anything older is dropped so
+  /// the analyzer never falls behind the source.
+  fn read_frames(&mut self, buffer: &mut Vec<f32>, max_frames: usize) -> usize;
+
+  /// Handles a transport control, sources that can not be controlled ignore it.
+  fn control(&mut self, _command: PlaybackCommand) {}
+
+  fn status(&self) -> SourceStatus {
+    SourceStatus::Connected
+  }
+}
@@ -0,0 +1,82 @@
+use std::collections::VecDeque;
+
+/// Number of frequency bands the stereo position is measured in
+pub const PAN_BAND_COUNT: usize = 16;
+
+/// Where the sound sits in the stereo field.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StereoImage {
+  /// RMS level of the mid signal, `(left + right) / 2`
+  pub mid: f32,
+  /// RMS level of the side signal, `(left - right) / 2`
+  pub side: f32,
+  /// Phase correlation from -1 (opposite phase) over 0 (unrelated or silent) to 1 (mono)
+  pub correlation: f32,
+  /// Pan position from -1 (left) to 1 (right) of each band, from low to high frequencies
+  pub band_pan: [f32; PAN_BAND_COUNT],
+}
+
+impl StereoImage {
+  /// Analyzes the first two `channels` and their `spectra`, a single channel is treated as
+  /// centered mono.
+  pub fn analyze(channels: &[VecDeque<f32>], spectra: &[Vec<f32>]) -> Self {
+    let (left, right) = match channels {
+      [] => return Self::default(),
+      [mono] => (mono, mono),
+      [left, right, ..] => (left, right),
+    };
+
+    let mut mid_power = 0.0;
+    let mut side_power = 0.0;
+    let mut left_power = 0.0;
+    let mut right_power = 0.0;
+    let mut cross_power = 0.0;
+    for (left, right) in left.iter().zip(right) {
+      let mid = (left + right) * 0.5;
+      let side = (left - right) * 0.5;
+      mid_power += mid * mid;
+      side_power += side * side;
+      left_power += left * left;
+      right_power += right * right;
+      cross_power += left * right;
+    }
+
+    let len = left.len().min(right.len()).max(1) as f32;
+    let norm = (left_power * right_power).sqrt();
+    let correlation = if norm > f32::EPSILON {
+      (cross_power / norm).clamp(-1.0, 1.0)
+    } else {
+      0.0
+    };
+
+    let band_pan = match spectra {
+      [left, right, ..] => band_pan(left, right),
+      _ => [0.0; PAN_BAND_COUNT],
+    };
+
+    Self {
+      mid: (mid_power / len).sqrt(),
+      side: (side_power / len).sqrt(),
+      correlation,
+      band_pan,
+    }
+  }
+}
+
+/// Compares the magnitude of both channels in equally sized slices of their spectra.
+fn band_pan(left: &[f32], right: &[f32]) -> [f32; PAN_BAND_COUNT] {
+  let len = left.len().min(right.len());
+  let mut band_pan = [0.0; PAN_BAND_COUNT];
+
+  for (band, pan) in band_pan.iter_mut().enumerate() {
+    let range = band * len / PAN_BAND_COUNT..(band + 1) * len / PAN_BAND_COUNT;
+    let left: f32 = left[range.clone()].iter().sum();
+    let right: f32 = right[range].iter().sum();
+
+    if left + right > f32::EPSILON {
+      *pan = (right - left) / (right + left);
+    }
+  }
+
+  band_pan
+}
@@ -0,0 +1,243 @@
+use std::{
+  collections::VecDeque,
+  time::{Duration, Instant},
+};
+
+use super::Beat;
+
+/// Rate the onset envelope is resampled to, analysis frames don't arrive at a fixed rate
+const ENVELOPE_RATE: f32 = 100.0;
+/// Length of the onset envelope the tempo is estimated from
+const ENVELOPE_DURATION: Duration = Duration::from_secs(8);
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+/// Tempo the estimates are pulled towards to avoid picking half or double the tempo
+const PREFERRED_BPM: f32 = 120.0;
+/// Tempo the beat phase runs at until a tempo was estimated
+const DEFAULT_BPM: f32 = 120.0;
+/// Time constant for following a changed tempo
+const TEMPO_SMOOTHING: Duration = Duration::from_secs(2);
+/// Share of the phase error corrected on every onset, scaled by the confidence
+const PHASE_CORRECTION: f32 = 0.25;
+/// Onsets further than this share of a beat from the expected beat don't count for the bar
+const MAX_ACCENT_ERROR: f32 = 0.2;
+const BEATS_PER_BAR: u64 = 4;
+/// Weight of the newest bar in the average onset strength of every beat of the bar
+const BAR_ACCENT_RATE: f32 = 0.1;
+
+/// Estimated tempo and the position in the beat and bar.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Tempo {
+  /// 0 until a tempo was estimated
+  pub bpm: f32,
+  /// How periodic the onsets are, from 0 to 1
+  pub confidence: f32,
+  /// Position within the current beat, 0 on the beat
+  pub beat_phase: f32,
+  /// Position within the current 4/4 bar, 0 on the estimated downbeat
+  pub bar_phase: f32,
+  /// Beats since the start including the phase, keeps increasing smoothly
+  pub beats: f32,
+}
+
+/// Estimates the tempo by autocorrelating the onset envelope and follows the beat phase
+/// with a free-running oscillator that is nudged towards detected onsets.
+pub struct TempoTracker {
+  envelope: VecDeque<f32>,
+  envelope_remainder: f32,
+  /// Seconds per beat, `None` until estimated
+  period: Option<f32>,
+  confidence: f32,
+  beat_phase: f32,
+  beat_index: u64,
+  /// Exponentially weighted onset strength on each beat of the bar, the strongest on
+  /// average is taken as the downbeat
+  bar_accents: [f32; BEATS_PER_BAR as usize],
+  /// Sum of the weights in `bar_accents`, to turn them into unbiased averages
+  bar_weights: [f32; BEATS_PER_BAR as usize],
+  last_onset_count: u32,
+  last_update: Option<Instant>,
+}
+
+impl TempoTracker {
+  pub fn new() -> Self {
+    Self {
+      envelope: VecDeque::new(),
+      envelope_remainder: 0.0,
+      period: None,
+      confidence: 0.0,
+      beat_phase: 0.0,
+      beat_index: 0,
+      bar_accents: [0.0; BEATS_PER_BAR as usize],
+      bar_weights: [0.0; BEATS_PER_BAR as usize],
+      last_onset_count: 0,
+      last_update: None,
+    }
+  }
+
+  /// Adds the onset `flux` of the newest analysis frame and advances the beat phase.
+  pub fn process(&mut self, flux: f32, beat: &Beat, now: Instant) -> Tempo {
+    let elapsed = self
+      .last_update
+      .map_or(0.0, |last_update| (now - last_update).as_secs_f32());
+    self.last_update = Some(now);
+
+    self.extend_envelope(flux, elapsed);
+
+    if let Some((period, confidence)) = self.estimate_period() {
+      let factor = 1.0 - (-elapsed / TEMPO_SMOOTHING.as_secs_f32()).exp();
+      self.period = Some(match self.period {
+        // Follow in the log domain, so speeding up and slowing down take as long
+        Some(current) => (current.ln() + (period.ln() - current.ln()) * factor).exp(),
+        None => period,
+      });
+      self.confidence = confidence;
+    }
+
+    let period = self.period.unwrap_or(60.0 / DEFAULT_BPM);
+    self.beat_phase += elapsed / period;
+    while self.beat_phase >= 1.0 {
+      self.beat_phase -= 1.0;
+      self.beat_index += 1;
+      // Onsets on this beat add to the average again
+      let beat_in_bar = (self.beat_index % BEATS_PER_BAR) as usize;
+      self.bar_accents[beat_in_bar] *= 1.0 - BAR_ACCENT_RATE;
+      self.bar_weights[beat_in_bar] =
+        self.bar_weights[beat_in_bar] * (1.0 - BAR_ACCENT_RATE) + BAR_ACCENT_RATE;
+    }
+
+    if beat.count != self.last_onset_count {
+      self.last_onset_count = beat.count;
+      self.align_to_onset(flux);
+    }
+
+    self.tempo()
+  }
+
+  /// Samples `flux` into the fixed rate envelope for the time since the last frame.
+  ///
+  /// Frames closer together than one envelope sample only carry their time over to the
+  /// next one, so the envelope keeps real time at any analysis rate.
+  fn extend_envelope(&mut self, flux: f32, elapsed: f32) {
+    let max_len = (ENVELOPE_DURATION.as_secs_f32() * ENVELOPE_RATE) as usize;
+
+    let samples = elapsed * ENVELOPE_RATE + self.envelope_remainder;
+    self.envelope_remainder = samples.fract();
+    for _ in 0..(samples as usize).min(max_len) {
+      self.envelope.push_back(flux);
+    }
+
+    let excess = self.envelope.len().saturating_sub(max_len);
+    self.envelope.drain(0..excess);
+  }
+
+  /// Finds the beat period with the strongest autocorrelation of the envelope, returns it
+  /// in seconds with the normalized correlation as confidence.
+  fn estimate_period(&self) -> Option<(f32, f32)> {
+    let min_lag = (60.0 / MAX_BPM * ENVELOPE_RATE) as usize;
+    let max_lag = (60.0 / MIN_BPM * ENVELOPE_RATE) as usize;
+    // Wait for a few beats worth of envelope
+    if self.envelope.len() < max_lag * 3 {
+      return None;
+    }
+
+    let mean = self.envelope.iter().sum::<f32>() / self.envelope.len() as f32;
+    let envelope: Vec<f32> = self.envelope.iter().map(|flux| flux - mean).collect();
+    let energy: f32 = envelope.iter().map(|flux| flux * flux).sum();
+    if energy <= f32::EPSILON {
+      return None;
+    }
+
+    let correlations: Vec<f32> = (min_lag - 1..=max_lag + 1)
+      .map(|lag| {
+        let sum: f32 = envelope[lag..]
+          .iter()
+          .zip(&envelope)
+          .map(|(current, earlier)| current * earlier)
+          .sum();
+        // Shorter overlaps at long lags would otherwise be penalized
+        sum / energy * envelope.len() as f32 / (envelope.len() - lag) as f32
+      })
+      .collect();
+
+    let (best, weighted) = (1..correlations.len() - 1)
+      .map(|index| {
+        let bpm = 60.0 * ENVELOPE_RATE / (index + min_lag - 1) as f32;
+        // Log-Gaussian prior around the preferred tempo
+        let octaves = (bpm / PREFERRED_BPM).log2();
+        (
+          index,
+          correlations[index] * (-0.5 * (octaves / 0.9).powi(2)).exp(),
+        )
+      })
+      .fold((0, f32::MIN), |best, candidate| {
+        if candidate.1 > best.1 {
+          candidate
+        } else {
+          best
+        }
+      });
+    if weighted <= 0.0 {
+      return None;
+    }
+
+    // Parabolic interpolation between the lags around the peak
+    let (previous, peak, next) = (
+      correlations[best - 1],
+      correlations[best],
+      correlations[best + 1],
+    );
+    let curvature = previous - 2.0 * peak + next;
+    let offset = if curvature < 0.0 {
+      (0.5 * (previous - next) / curvature).clamp(-0.5, 0.5)
+    } else {
+      0.0
+    };
+
+    let lag = (best + min_lag - 1) as f32 + offset;
+    Some((lag / ENVELOPE_RATE, peak.clamp(0.0, 1.0)))
+  }
+
+  /// Pulls the beat phase towards an onset, and remembers the strength of onsets close to
+  /// an expected beat for the downbeat estimate.
+  fn align_to_onset(&mut self, flux: f32) {
+    // Distance to the nearest beat, negative when the onset came early
+    let (error, beat_index) = if self.beat_phase < 0.5 {
+      (self.beat_phase, self.beat_index)
+    } else {
+      (self.beat_phase - 1.0, self.beat_index + 1)
+    };
+    let correction = PHASE_CORRECTION * self.confidence.max(0.2);
+    self.beat_phase -= error * correction;
+    if self.beat_phase < 0.0 {
+      self.beat_phase += 1.0;
+      self.beat_index = self.beat_index.saturating_sub(1);
+    } else if self.beat_phase >= 1.0 {
+      self.beat_phase -= 1.0;
+      self.beat_index += 1;
+    }
+
+    if error.abs() <= MAX_ACCENT_ERROR {
+      self.bar_accents[(beat_index % BEATS_PER_BAR) as usize] += flux * BAR_ACCENT_RATE;
+    }
+  }
+
+  fn tempo(&self) -> Tempo {
+    let average_accent = |beat: u64| {
+      let beat = beat as usize;
+      self.bar_accents[beat] / self.bar_weights[beat].max(f32::EPSILON)
+    };
+    let downbeat = (0..BEATS_PER_BAR)
+      .max_by(|a, b| average_accent(*a).total_cmp(&average_accent(*b)))
+      .unwrap_or(0);
+    let beat_in_bar = (self.beat_index + BEATS_PER_BAR - downbeat) % BEATS_PER_BAR;
+
+    Tempo {
+      bpm: self.period.map_or(0.0, |period| 60.0 / period),
+      confidence: self.confidence,
+      beat_phase: self.beat_phase,
+      bar_phase: (beat_in_bar as f32 + self.beat_phase) / BEATS_PER_BAR as f32,
+      beats: self.beat_index as f32 + self.beat_phase,
+    }
+  }
+}
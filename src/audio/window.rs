@@ -0,0 +1,83 @@
+use std::{f32::consts::TAU, str::FromStr};
+
+/// Coherent gain of the Hamming window audioviz applied before, windows are scaled to match
+/// it so switching them does not change the overall level.
+const REFERENCE_GAIN: f32 = 0.54;
+
+/// Window function applied to every block of samples before the FFT.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WindowFunction {
+  Hann,
+  /// What audioviz applies on its own
+  #[default]
+  Hamming,
+  /// 4-term Blackman-Harris, very low side lobes for a wider main lobe
+  BlackmanHarris,
+  /// Accurate peak amplitudes at the cost of frequency resolution
+  FlatTop,
+}
+
+impl WindowFunction {
+  pub fn next(self) -> Self {
+    match self {
+      Self::Hann => Self::Hamming,
+      Self::Hamming => Self::BlackmanHarris,
+      Self::BlackmanHarris => Self::FlatTop,
+      Self::FlatTop => Self::Hann,
+    }
+  }
+
+  /// Cosine sum coefficients `a0 - a1 cos(x) + a2 cos(2x) - ...` of the window.
+  fn coefficients(self) -> &'static [f32] {
+    match self {
+      Self::Hann => &[0.5, 0.5],
+      Self::Hamming => &[0.54, 0.46],
+      Self::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+      Self::FlatTop => &[0.21557895, 0.41663158, 0.27726316, 0.083578947, 0.006947368],
+    }
+  }
+
+  /// Computes the window for blocks of `len` samples.
+  pub fn build(self, len: usize) -> Vec<f32> {
+    let coefficients = self.coefficients();
+    let mut window: Vec<f32> = (0..len)
+      .map(|index| {
+        let x = TAU * index as f32 / (len.max(2) - 1) as f32;
+        coefficients
+          .iter()
+          .enumerate()
+          .map(|(term, coefficient)| {
+            let sign = if term % 2 == 0 { 1.0 } else { -1.0 };
+            sign * coefficient * (term as f32 * x).cos()
+          })
+          .sum()
+      })
+      .collect();
+
+    let gain = window.iter().sum::<f32>() / len.max(1) as f32;
+    if gain > 0.0 {
+      window
+        .iter_mut()
+        .for_each(|value| *value *= REFERENCE_GAIN / gain);
+    }
+
+    window
+  }
+}
+
+impl FromStr for WindowFunction {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "hann" => Ok(Self::Hann),
+      "hamming" => Ok(Self::Hamming),
+      "blackman-harris" => Ok(Self::BlackmanHarris),
+      "flat-top" => Ok(Self::FlatTop),
+      _ => Err(format!(
+        "unknown window function `{}`, expected hann, hamming, blackman-harris or flat-top",
+        value
+      )),
+    }
+  }
+}
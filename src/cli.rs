@@ -0,0 +1,301 @@
+use std::{env, process, time::Duration};
+
+use crate::audio::{
+  AnalysisConfig, BandRange, DeviceConfig, FileConfig, GeneratorConfig, MAX_FFT_SIZE, MIN_FFT_SIZE,
+  PcmConfig, StereoMode,
+};
+
+const USAGE: &str = "\
+Usage: julia-visualizer [OPTIONS]
+
+Options:
+  --list-devices        List every audio host and device with its supported configs
+  --host <NAME>         Audio host to use, defaults to the platform default
+  --device <DEVICE>     Device to capture from, by index, exact name or part of its name
+  --file <PATH>         Visualize a WAV, FLAC, Ogg Vorbis or MP3 file or .m3u playlist instead,
+                        can be repeated
+  --play                Play files through the default output device while visualizing
+  --loop <MODE>         Loop mode for files: off, track or playlist [default: off]
+  --pcm <PATH>          Read raw interleaved PCM from a file or named pipe, `-` for stdin
+  --pcm-format <FMT>    PCM sample format: u8, s8, s16le, s16be, s24le, s24be, s32le, s32be,
+                        f32le or f32be [default: s16le]
+  --pcm-rate <HZ>       PCM sample rate [default: 44100]
+  --pcm-channels <N>    PCM channel count [default: 2]
+  --generator <SIGNAL>  Visualize a test signal: sine:<hz>, sweep:<start hz>:<end hz>:<seconds>,
+                        white, pink, impulse:<bpm> or chord:<hz>,<hz>,...
+  --stereo <MODE>       Test signal stereo mode: mono, left, right, anti-phase or decorrelated
+                        [default: mono]
+  --channel-mode <MODE> Pass channels to the shader mixed into one or split [default: mixed]
+  --window <WINDOW>     FFT window function: hann, hamming, blackman-harris or flat-top
+                        [default: hamming]
+  --fft-size <N>        Samples per FFT block, a power of two from 256 to 32768 [default: 4096]
+  --hop <N>             Samples between analyzed blocks, at most the FFT size [default: 512]
+  --backend <BACKEND>   Spectrum transform: fft or cqt (constant-Q) [default: fft]
+  --bins-per-octave <N> Constant-Q resolution [default: 24]
+  --layout <LAYOUT>     Spectrum band layout: linear, log, mel, bark or third-octave
+                        [default: linear]
+  --min-freq <HZ>       Lowest frequency in the spectrum [default: 20]
+  --max-freq <HZ>       Highest frequency in the spectrum [default: 20000]
+  --band-count <N>      Spectrum bands, ignored by third-octave [default: window width]
+  --attack <MS>         Spectrum attack time constant [default: 10]
+  --release <MS>        Spectrum release time constant [default: 150]
+  --peak-hold <MS>      How long spectrum peaks are held [default: 500]
+  --peak-decay <MS>     Time constant of falling spectrum peaks [default: 500]
+  --beat-sensitivity <X> Standard deviations above the recent spectral flux that count as a
+                        beat [default: 1.5]
+  --band <NAME>=<MIN>-<MAX>
+                        Frequency range in Hz of a named band: sub, bass, low-mid, mid,
+                        presence or air, can be repeated
+  --agc-target <LUFS>   Short-term loudness the automatic gain control normalizes to
+                        [default: -18]
+  --agc-speed <MS>      Time constant of the automatic gain control [default: 3000]
+  --agc-min-gain <DB>   Lowest automatic gain [default: -12]
+  --agc-max-gain <DB>   Highest automatic gain [default: 24]
+  --no-agc              Start with the automatic gain control bypassed
+  --meters              Start with the level and loudness meters shown
+  --silence-threshold <DB>
+                        Peak level below which the input counts as silent [default: -60]
+  --silence-hold <MS>   Silence before switching to the idle animation [default: 5000]
+  --idle-fps <N>        Frame rate limit once idle, unlimited by default
+  -h, --help            Print this help
+
+Playback keys: Space pause, Left/Right seek 5s, L cycle loop mode, N/P next/previous track
+Analysis keys: W cycle window function, [/] halve/double FFT size, ,/. halve/double hop size,
+               G toggle automatic gain control, M toggle meters";
+
+#[derive(Debug, Default)]
+pub struct Options {
+  pub list_devices: bool,
+  pub device: DeviceConfig,
+  pub file: FileConfig,
+  pub pcm: Option<PcmConfig>,
+  pub generator: Option<GeneratorConfig>,
+  pub analysis: AnalysisConfig,
+  pub show_meters: bool,
+  /// Frame rate limit once idle
+  pub idle_frame_rate: Option<f32>,
+}
+
+impl Options {
+  /// Parses the process arguments, exiting with a usage message when they are invalid.
+  pub fn from_args() -> Self {
+    match Self::parse(env::args().skip(1)) {
+      Ok(options) => options,
+      Err(error) => {
+        eprintln!("error: {}\n\n{}", error, USAGE);
+        process::exit(2);
+      }
+    }
+  }
+
+  fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+    let mut options = Self::default();
+    let mut pcm = PcmConfig::default();
+    let mut pcm_input = false;
+    let mut pcm_options_used = false;
+    let mut stereo = None;
+
+    while let Some(arg) = args.next() {
+      match arg.as_str() {
+        "-h" | "--help" => {
+          println!("{}", USAGE);
+          process::exit(0);
+        }
+        "--list-devices" => options.list_devices = true,
+        "--host" => options.device.host = Some(next_value(&mut args, &arg)?),
+        "--device" => options.device.device = Some(next_value(&mut args, &arg)?.parse()?),
+        "--file" => options.file.paths.push(next_value(&mut args, &arg)?.into()),
+        "--play" => options.file.playback = true,
+        "--loop" => options.file.loop_mode = next_value(&mut args, &arg)?.parse()?,
+        "--pcm" => {
+          let path = next_value(&mut args, &arg)?;
+          pcm.path = (path != "-").then(|| path.into());
+          pcm_input = true;
+        }
+        "--pcm-format" => {
+          pcm.format = next_value(&mut args, &arg)?.parse()?;
+          pcm_options_used = true;
+        }
+        "--pcm-rate" => {
+          pcm.sample_rate = parse_positive(&next_value(&mut args, &arg)?, &arg)?;
+          pcm_options_used = true;
+        }
+        "--pcm-channels" => {
+          pcm.channel_count = parse_positive(&next_value(&mut args, &arg)?, &arg)?;
+          pcm_options_used = true;
+        }
+        "--generator" => {
+          options.generator = Some(GeneratorConfig {
+            signal: next_value(&mut args, &arg)?.parse()?,
+            stereo: StereoMode::default(),
+          });
+        }
+        "--stereo" => stereo = Some(next_value(&mut args, &arg)?.parse()?),
+        "--channel-mode" => {
+          options.analysis.channel_mode = next_value(&mut args, &arg)?.parse()?;
+        }
+        "--window" => options.analysis.window = next_value(&mut args, &arg)?.parse()?,
+        "--fft-size" => {
+          let fft_size: usize = parse_positive(&next_value(&mut args, &arg)?, &arg)?;
+          if !fft_size.is_power_of_two() || !(MIN_FFT_SIZE..=MAX_FFT_SIZE).contains(&fft_size) {
+            return Err(format!(
+              "`--fft-size` requires a power of two from {} to {}, got `{}`",
+              MIN_FFT_SIZE, MAX_FFT_SIZE, fft_size
+            ));
+          }
+          options.analysis.fft_size = fft_size;
+        }
+        "--hop" => {
+          options.analysis.hop_size = parse_positive(&next_value(&mut args, &arg)?, &arg)?;
+        }
+        "--backend" => options.analysis.backend = next_value(&mut args, &arg)?.parse()?,
+        "--bins-per-octave" => {
+          options.analysis.bins_per_octave = parse_positive(&next_value(&mut args, &arg)?, &arg)?;
+        }
+        "--layout" => options.analysis.layout.layout = next_value(&mut args, &arg)?.parse()?,
+        "--min-freq" => {
+          options.analysis.layout.min_frequency =
+            parse_positive_number(&next_value(&mut args, &arg)?, &arg)?;
+        }
+        "--max-freq" => {
+          options.analysis.layout.max_frequency =
+            parse_positive_number(&next_value(&mut args, &arg)?, &arg)?;
+        }
+        "--band-count" => {
+          options.analysis.layout.band_count =
+            Some(parse_positive(&next_value(&mut args, &arg)?, &arg)?);
+        }
+        "--attack" => options.analysis.smoothing.attack = parse_millis(&mut args, &arg)?,
+        "--release" => options.analysis.smoothing.release = parse_millis(&mut args, &arg)?,
+        "--peak-hold" => options.analysis.smoothing.peak_hold = parse_millis(&mut args, &arg)?,
+        "--peak-decay" => options.analysis.smoothing.peak_decay = parse_millis(&mut args, &arg)?,
+        "--beat-sensitivity" => {
+          options.analysis.onset.sensitivity =
+            parse_positive_number(&next_value(&mut args, &arg)?, &arg)?;
+        }
+        "--band" => {
+          let range: BandRange = next_value(&mut args, &arg)?.parse()?;
+          options.analysis.bands.set_range(range);
+        }
+        "--agc-target" => {
+          options.analysis.gain.target = parse_number(&next_value(&mut args, &arg)?, &arg)?;
+        }
+        "--agc-speed" => options.analysis.gain.speed = parse_millis(&mut args, &arg)?,
+        "--agc-min-gain" => {
+          options.analysis.gain.min_gain = parse_number(&next_value(&mut args, &arg)?, &arg)?;
+        }
+        "--agc-max-gain" => {
+          options.analysis.gain.max_gain = parse_number(&next_value(&mut args, &arg)?, &arg)?;
+        }
+        "--no-agc" => options.analysis.gain.enabled = false,
+        "--meters" => options.show_meters = true,
+        "--silence-threshold" => {
+          options.analysis.silence.threshold = parse_number(&next_value(&mut args, &arg)?, &arg)?;
+        }
+        "--silence-hold" => options.analysis.silence.hold = parse_millis(&mut args, &arg)?,
+        "--idle-fps" => {
+          options.idle_frame_rate =
+            Some(parse_positive_number(&next_value(&mut args, &arg)?, &arg)?);
+        }
+        _ => return Err(format!("unknown argument `{}`", arg)),
+      }
+    }
+
+    if options.file.paths.is_empty()
+      && (options.file.playback || options.file.loop_mode != Default::default())
+    {
+      return Err("`--play` and `--loop` require `--file`".to_string());
+    }
+
+    if pcm_input {
+      options.pcm = Some(pcm);
+    } else if pcm_options_used {
+      return Err("`--pcm-format`, `--pcm-rate` and `--pcm-channels` require `--pcm`".to_string());
+    }
+
+    match (&mut options.generator, stereo) {
+      (Some(generator), Some(stereo)) => generator.stereo = stereo,
+      (None, Some(_)) => return Err("`--stereo` requires `--generator`".to_string()),
+      _ => (),
+    }
+
+    if options.analysis.hop_size > options.analysis.fft_size {
+      return Err("`--hop` can not be larger than the FFT size".to_string());
+    }
+
+    if options.analysis.layout.min_frequency >= options.analysis.layout.max_frequency {
+      return Err("`--min-freq` has to be lower than `--max-freq`".to_string());
+    }
+
+    if options.analysis.gain.min_gain > options.analysis.gain.max_gain {
+      return Err("`--agc-min-gain` can not be higher than `--agc-max-gain`".to_string());
+    }
+
+    let source_count = [
+      !options.file.paths.is_empty(),
+      options.pcm.is_some(),
+      options.generator.is_some(),
+    ]
+    .into_iter()
+    .filter(|used| *used)
+    .count();
+    if source_count > 1 {
+      return Err("only one of `--file`, `--pcm` and `--generator` can be used".to_string());
+    }
+
+    Ok(options)
+  }
+}
+
+fn next_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+  args
+    .next()
+    .ok_or_else(|| format!("`{}` requires a value", flag))
+}
+
+/// Reads the next argument as a duration in milliseconds, zero included.
+fn parse_millis(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<Duration, String> {
+  let value = next_value(args, flag)?;
+  match value.parse::<f32>() {
+    Ok(millis) if millis >= 0.0 && millis.is_finite() => {
+      Ok(Duration::from_secs_f32(millis / 1000.0))
+    }
+    _ => Err(format!(
+      "`{}` requires a duration in milliseconds, got `{}`",
+      flag, value
+    )),
+  }
+}
+
+/// Parses a finite number, negative ones included.
+fn parse_number(value: &str, flag: &str) -> Result<f32, String> {
+  match value.parse::<f32>() {
+    Ok(number) if number.is_finite() => Ok(number),
+    _ => Err(format!("`{}` requires a number, got `{}`", flag, value)),
+  }
+}
+
+/// Parses a finite number greater than zero.
+fn parse_positive_number(value: &str, flag: &str) -> Result<f32, String> {
+  match value.parse::<f32>() {
+    Ok(number) if number > 0.0 && number.is_finite() => Ok(number),
+    _ => Err(format!(
+      "`{}` requires a positive number, got `{}`",
+      flag, value
+    )),
+  }
+}
+
+/// Parses an integer greater than zero.
+fn parse_positive<T>(value: &str, flag: &str) -> Result<T, String>
+where
+  T: std::str::FromStr + Default + PartialOrd,
+{
+  match value.parse() {
+    Ok(number) if number > T::default() => Ok(number),
+    _ => Err(format!(
+      "`{}` requires a positive number, got `{}`",
+      flag, value
+    )),
+  }
+}
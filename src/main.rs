@@ -1,15 +1,25 @@
-use std::{sync::Arc, time::Instant};
+use std::{
+  process,
+  sync::Arc,
+  time::{Duration, Instant},
+};
 
-use audio::AudioProcessor;
+use audio::{
+  AnalysisThread, AudioError, AudioProcessor, AudioSource, CaptureSource, FileSource, PcmSource,
+  PlaybackCommand, SignalGenerator, SnapshotInterpolator, SourceStatus,
+};
+use cli::Options;
 use renderer::Renderer;
 use winit::{
   application::ApplicationHandler,
-  event::WindowEvent,
+  event::{KeyEvent, StartCause, WindowEvent},
   event_loop::{ActiveEventLoop, ControlFlow, EventLoop},
+  keyboard::{Key, NamedKey},
   window::{Window, WindowId},
 };
 
 mod audio;
+mod cli;
 mod renderer;
 
 struct State {
@@ -17,21 +27,26 @@ struct State {
   size: winit::dpi::PhysicalSize<u32>,
   renderer: Renderer,
   start_instant: Instant,
-  audio_processor: AudioProcessor,
+  analysis: AnalysisThread,
+  snapshots: SnapshotInterpolator,
+  audio_status: SourceStatus,
 }
 
 impl State {
-  async fn new(window: Arc<Window>) -> State {
+  async fn new(window: Arc<Window>, analysis: AnalysisThread, show_meters: bool) -> State {
     let mut state = State {
       renderer: Renderer::new(window.clone()).await,
       size: window.inner_size(),
       window,
       start_instant: Instant::now(),
-      audio_processor: AudioProcessor::init(),
+      analysis,
+      snapshots: SnapshotInterpolator::default(),
+      audio_status: SourceStatus::Connected,
     };
 
     state.configure_surface();
     state.configure_audio_processor();
+    state.renderer.set_show_meters(show_meters);
 
     state
   }
@@ -45,9 +60,10 @@ impl State {
   }
 
   fn configure_audio_processor(&mut self) {
+    let width = self.size.width as usize;
     self
-      .audio_processor
-      .set_resolution(Some(self.size.width as usize));
+      .analysis
+      .send(move |processor| processor.set_resolution(Some(width)));
   }
 
   fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
@@ -57,22 +73,94 @@ impl State {
     self.configure_audio_processor();
   }
 
+  fn handle_key(&mut self, event: KeyEvent) {
+    if !event.state.is_pressed() {
+      return;
+    }
+
+    let analysis = &self.analysis;
+    match event.logical_key.as_ref() {
+      Key::Character("w") if !event.repeat => analysis.send(AudioProcessor::cycle_window_function),
+      Key::Character("[") => {
+        analysis.send(|processor| processor.set_fft_size(processor.fft_size() / 2))
+      }
+      Key::Character("]") => {
+        analysis.send(|processor| processor.set_fft_size(processor.fft_size() * 2))
+      }
+      Key::Character(",") => {
+        analysis.send(|processor| processor.set_hop_size(processor.hop_size() / 2))
+      }
+      Key::Character(".") => {
+        analysis.send(|processor| processor.set_hop_size(processor.hop_size() * 2))
+      }
+      Key::Character("g") if !event.repeat => analysis.send(AudioProcessor::toggle_auto_gain),
+      Key::Character("m") if !event.repeat => {
+        let show_meters = !self.renderer.show_meters();
+        self.renderer.set_show_meters(show_meters);
+      }
+      _ => self.handle_playback_key(&event),
+    }
+  }
+
+  fn handle_playback_key(&mut self, event: &KeyEvent) {
+    let command = match event.logical_key.as_ref() {
+      Key::Named(NamedKey::Space) if !event.repeat => PlaybackCommand::TogglePause,
+      Key::Named(NamedKey::ArrowLeft) => PlaybackCommand::SeekBy(-5.0),
+      Key::Named(NamedKey::ArrowRight) => PlaybackCommand::SeekBy(5.0),
+      Key::Character("l") if !event.repeat => PlaybackCommand::CycleLoopMode,
+      Key::Character("n") if !event.repeat => PlaybackCommand::NextTrack,
+      Key::Character("p") if !event.repeat => PlaybackCommand::PreviousTrack,
+      _ => return,
+    };
+
+    self
+      .analysis
+      .send(move |processor| processor.control(command));
+  }
+
+  fn update_audio_status(&mut self) {
+    let status = self.analysis.status();
+    if status == self.audio_status {
+      return;
+    }
+
+    self.audio_status = status;
+    self.window.set_title(&window_title(status));
+
+    // Don't keep showing whatever was captured last
+    if status == SourceStatus::Disconnected {
+      self.snapshots.clear();
+      self.renderer.clear_audio_data();
+    }
+  }
+
   fn render(&mut self) {
-    let spectrum = self.audio_processor.process_data();
-    if let Some(spectrum) = spectrum {
-      let waveform = self.audio_processor.get_waveform();
-      self.renderer.update_audio_data(spectrum, waveform);
+    let now = Instant::now();
+    if let Some(snapshot) = self.analysis.take_snapshot() {
+      self.snapshots.push(snapshot, now);
     }
+    self.update_audio_status();
+    if let Some(frame) = self.snapshots.frame(now) {
+      self.renderer.update_audio_frame(&frame);
+    }
+    self.renderer.set_activity(self.analysis.activity());
 
     let surface_texture = self.renderer.render(self.start_instant.elapsed());
     self.window.pre_present_notify();
     surface_texture.present();
   }
+
+  /// Whether the idle animation has completely faded in.
+  fn is_fully_idle(&self) -> bool {
+    self.analysis.is_idle() && self.analysis.activity() <= 0.0
+  }
 }
 
-#[derive(Default)]
 struct App {
   state: Option<State>,
+  analysis: Option<AnalysisThread>,
+  show_meters: bool,
+  idle_frame_rate: Option<f32>,
 }
 
 impl ApplicationHandler for App {
@@ -80,16 +168,29 @@ impl ApplicationHandler for App {
     // Create window object
     let window = Arc::new(
       event_loop
-        .create_window(Window::default_attributes())
+        .create_window(
+          Window::default_attributes().with_title(window_title(SourceStatus::Connected)),
+        )
         .unwrap(),
     );
 
-    let state = pollster::block_on(State::new(window.clone()));
+    let analysis = self
+      .analysis
+      .take()
+      .expect("the application should only be resumed once");
+    let state = pollster::block_on(State::new(window.clone(), analysis, self.show_meters));
     self.state = Some(state);
 
     window.request_redraw();
   }
 
+  fn new_events(&mut self, _event_loop: &ActiveEventLoop, cause: StartCause) {
+    // The next frame of a limited idle frame rate is due
+    if let (StartCause::ResumeTimeReached { .. }, Some(state)) = (cause, &self.state) {
+      state.get_window().request_redraw();
+    }
+  }
+
   fn window_event(&mut self, event_loop: &ActiveEventLoop, _id: WindowId, event: WindowEvent) {
     let state = self.state.as_mut().unwrap();
     match event {
@@ -99,9 +200,21 @@ impl ApplicationHandler for App {
       }
       WindowEvent::RedrawRequested => {
         state.render();
-        // Emits a new redraw requested event.
-        state.get_window().request_redraw();
+
+        // The idle animation is slow enough for a lower frame rate, new audio is noticed
+        // on the next frame
+        match self.idle_frame_rate.filter(|_| state.is_fully_idle()) {
+          Some(frame_rate) => event_loop.set_control_flow(ControlFlow::WaitUntil(
+            Instant::now() + Duration::from_secs_f32(1.0 / frame_rate),
+          )),
+          None => {
+            event_loop.set_control_flow(ControlFlow::Poll);
+            // Emits a new redraw requested event.
+            state.get_window().request_redraw();
+          }
+        }
       }
+      WindowEvent::KeyboardInput { event, .. } => state.handle_key(event),
       WindowEvent::Resized(size) => {
         // Reconfigures the size of the surface. We do not re-render
         // here as this event is always followed up by redraw request.
@@ -112,6 +225,29 @@ impl ApplicationHandler for App {
   }
 }
 
+fn window_title(status: SourceStatus) -> String {
+  match status {
+    SourceStatus::Connected => "Julia Visualizer".to_string(),
+    SourceStatus::Disconnected => "Julia Visualizer (audio disconnected)".to_string(),
+  }
+}
+
+fn open_audio_source(options: &Options) -> Result<Box<dyn AudioSource>, AudioError> {
+  if let Some(generator) = &options.generator {
+    return Ok(Box::new(SignalGenerator::new(generator.clone())));
+  }
+
+  if let Some(pcm) = &options.pcm {
+    return Ok(Box::new(PcmSource::open(pcm)));
+  }
+
+  if !options.file.paths.is_empty() {
+    return Ok(Box::new(FileSource::open(&options.file)?));
+  }
+
+  Ok(Box::new(CaptureSource::open(&options.device)?))
+}
+
 fn main() {
   // wgpu uses `log` for all of our logging, so we initialize a logger with the `env_logger` crate.
   //
@@ -119,6 +255,26 @@ fn main() {
   // documentation for more information.
   env_logger::init();
 
+  let options = Options::from_args();
+  if options.list_devices {
+    audio::list_devices();
+    return;
+  }
+
+  let show_meters = options.show_meters;
+  let idle_frame_rate = options.idle_frame_rate;
+  let analysis = AnalysisThread::spawn(move || {
+    let audio_source = open_audio_source(&options)?;
+    Ok(AudioProcessor::new(audio_source, &options.analysis))
+  });
+  let analysis = match analysis {
+    Ok(analysis) => analysis,
+    Err(error) => {
+      eprintln!("error: {}", error);
+      process::exit(1);
+    }
+  };
+
   let event_loop = EventLoop::new().unwrap();
 
   // When the current loop iteration finishes, immediately begin a new
@@ -127,6 +283,11 @@ fn main() {
   // possible, like games.
   event_loop.set_control_flow(ControlFlow::Poll);
 
-  let mut app = App::default();
+  let mut app = App {
+    state: None,
+    analysis: Some(analysis),
+    show_meters,
+    idle_frame_rate,
+  };
   event_loop.run_app(&mut app).unwrap();
 }
@@ -1,12 +1,16 @@
 use std::{sync::Arc, time::Duration};
 
 use audio_data::{AudioData, BindAudioData};
+use audio_features::{AudioFeatures, BindAudioFeatures};
 use extra_info::{BindExtraInfo, ExtraInfo};
 use mesh::{DrawMesh, Mesh};
 use wgpu::include_wgsl;
 use winit::window::Window;
 
+use crate::audio::AudioFrame;
+
 mod audio_data;
+mod audio_features;
 mod extra_info;
 mod mesh;
 
@@ -19,6 +23,8 @@ pub struct Renderer {
   mesh: Mesh,
   extra_info: ExtraInfo,
   audio_data: AudioData,
+  audio_features: AudioFeatures,
+  show_meters: bool,
 }
 
 impl Renderer {
@@ -41,11 +47,16 @@ impl Renderer {
     let shader = device.create_shader_module(include_wgsl!("shader.wgsl"));
 
     let extra_info = ExtraInfo::new(&device).await;
-    let audio_data = AudioData::new(&device, 1).await;
+    let audio_data = AudioData::new(&device).await;
+    let audio_features = AudioFeatures::new(&device).await;
 
     let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
       label: None,
-      bind_group_layouts: &[extra_info.layout(), audio_data.layout()],
+      bind_group_layouts: &[
+        extra_info.layout(),
+        audio_data.layout(),
+        audio_features.layout(),
+      ],
       push_constant_ranges: &[],
     });
 
@@ -87,6 +98,8 @@ impl Renderer {
       mesh,
       extra_info,
       audio_data,
+      audio_features,
+      show_meters: false,
     }
   }
 
@@ -104,7 +117,6 @@ impl Renderer {
     };
     self.surface.configure(&self.device, &surface_config);
     self.extra_info.update_resolution(size.cast(), &self.queue);
-    self.audio_data.resize(&self.device, size.width as usize);
   }
 
   pub fn render(&mut self, elapsed_time: Duration) -> wgpu::SurfaceTexture {
@@ -150,6 +162,7 @@ impl Renderer {
 
     renderpass.bind_extra_info(0, &self.extra_info);
     renderpass.bind_audio_data(1, &self.audio_data);
+    renderpass.bind_audio_features(2, &self.audio_features);
 
     renderpass.draw_mesh(&self.mesh);
 
@@ -161,8 +174,38 @@ impl Renderer {
     surface_texture
   }
 
-  pub fn update_audio_data(&self, spectrum: Vec<f32>, waveform: Vec<f32>) {
-    self.audio_data.update_spectrum(&spectrum, &self.queue);
-    self.audio_data.update_waveform(&waveform, &self.queue);
+  pub fn update_audio_frame(&mut self, frame: &AudioFrame) {
+    // Band layouts and the constant-Q backend don't produce one bin per pixel, so the
+    // buffers follow the frame rather than the window
+    let channel_len = |channels: &[Vec<f32>]| channels.first().map_or(0, Vec::len);
+    self.audio_data.resize(
+      &self.device,
+      &self.queue,
+      channel_len(&frame.spectrum),
+      channel_len(&frame.waveform),
+      frame.spectrum.len(),
+    );
+    self.audio_data.update(frame, &self.queue);
+    self.audio_features.update(frame, &self.queue);
+  }
+
+  pub fn show_meters(&self) -> bool {
+    self.show_meters
+  }
+
+  /// Shows or hides the level and loudness meter overlay.
+  pub fn set_show_meters(&mut self, show_meters: bool) {
+    self.show_meters = show_meters;
+    self.extra_info.update_show_meters(show_meters, &self.queue);
+  }
+
+  /// Fades between the audio reactive visuals at 1 and the idle animation at 0.
+  pub fn set_activity(&self, activity: f32) {
+    self.extra_info.update_activity(activity, &self.queue);
+  }
+
+  pub fn clear_audio_data(&self) {
+    self.audio_data.clear(&self.queue);
+    self.audio_features.clear(&self.queue);
   }
 }
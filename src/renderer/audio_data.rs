@@ -1,32 +1,92 @@
 use std::mem;
 
+use wgpu::util::DeviceExt;
+
+use crate::audio::AudioFrame;
+
+/// Per channel spectrum, waveform and spectrum peaks, stored channel after channel in one
+/// buffer each.
+///
+/// The peaks have one value per spectrum bin, the waveform has a length of its own.
 pub struct AudioData {
+  spectrum_len: usize,
+  waveform_len: usize,
+  channel_count: usize,
   spectrum_buffer: wgpu::Buffer,
   waveform_buffer: wgpu::Buffer,
+  peaks_buffer: wgpu::Buffer,
+  channel_count_buffer: wgpu::Buffer,
   audio_data_bind_group: wgpu::BindGroup,
   audio_data_bind_group_layout: wgpu::BindGroupLayout,
 }
 
 impl AudioData {
-  fn create_buffers(device: &wgpu::Device, size: usize) -> (wgpu::Buffer, wgpu::Buffer) {
+  fn create_buffers(
+    device: &wgpu::Device,
+    spectrum_len: usize,
+    waveform_len: usize,
+    channel_count: usize,
+  ) -> (wgpu::Buffer, wgpu::Buffer, wgpu::Buffer) {
+    // Storage buffers can not be empty
+    let buffer_size = |len: usize| ((len * channel_count).max(1) * mem::size_of::<f32>()) as u64;
+
     let spectrum_buffer = device.create_buffer(&wgpu::BufferDescriptor {
       label: Some("Spectrum Buffer"),
-      size: (size * mem::size_of::<f32>()) as u64,
-      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      size: buffer_size(spectrum_len),
+      usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
       mapped_at_creation: false,
     });
 
     let waveform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
       label: Some("Waveform Buffer"),
-      size: (size * mem::size_of::<f32>()) as u64,
-      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+      size: buffer_size(waveform_len),
+      usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+      mapped_at_creation: false,
+    });
+
+    let peaks_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+      label: Some("Peaks Buffer"),
+      size: buffer_size(spectrum_len),
+      usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
       mapped_at_creation: false,
     });
 
-    (spectrum_buffer, waveform_buffer)
+    (spectrum_buffer, waveform_buffer, peaks_buffer)
   }
 
-  pub async fn new(device: &wgpu::Device, size: usize) -> Self {
+  fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    spectrum_buffer: &wgpu::Buffer,
+    waveform_buffer: &wgpu::Buffer,
+    channel_count_buffer: &wgpu::Buffer,
+    peaks_buffer: &wgpu::Buffer,
+  ) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::Buffer(spectrum_buffer.as_entire_buffer_binding()),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::Buffer(waveform_buffer.as_entire_buffer_binding()),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: wgpu::BindingResource::Buffer(channel_count_buffer.as_entire_buffer_binding()),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: wgpu::BindingResource::Buffer(peaks_buffer.as_entire_buffer_binding()),
+        },
+      ],
+      label: Some("audio_data_bind_group"),
+    })
+  }
+
+  pub async fn new(device: &wgpu::Device) -> Self {
     let audio_data_bind_group_layout =
       device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         entries: &[
@@ -34,7 +94,7 @@ impl AudioData {
             binding: 0,
             visibility: wgpu::ShaderStages::FRAGMENT,
             ty: wgpu::BindingType::Buffer {
-              ty: wgpu::BufferBindingType::Uniform,
+              ty: wgpu::BufferBindingType::Storage { read_only: true },
               has_dynamic_offset: false,
               min_binding_size: None,
             },
@@ -43,6 +103,16 @@ impl AudioData {
           wgpu::BindGroupLayoutEntry {
             binding: 1,
             visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Storage { read_only: true },
+              has_dynamic_offset: false,
+              min_binding_size: None,
+            },
+            count: None,
+          },
+          wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::FRAGMENT,
             ty: wgpu::BindingType::Buffer {
               ty: wgpu::BufferBindingType::Uniform,
               has_dynamic_offset: false,
@@ -50,51 +120,148 @@ impl AudioData {
             },
             count: None,
           },
+          wgpu::BindGroupLayoutEntry {
+            binding: 3,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Storage { read_only: true },
+              has_dynamic_offset: false,
+              min_binding_size: None,
+            },
+            count: None,
+          },
         ],
-        label: Some("fragment_bind_group_layout"),
+        label: Some("audio_data_bind_group_layout"),
       });
 
-    let (spectrum_buffer, waveform_buffer) = Self::create_buffers(device, size);
-
-    let audio_data_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-      layout: &audio_data_bind_group_layout,
-      entries: &[
-        wgpu::BindGroupEntry {
-          binding: 0,
-          resource: wgpu::BindingResource::Buffer(spectrum_buffer.as_entire_buffer_binding()),
-        },
-        wgpu::BindGroupEntry {
-          binding: 1,
-          resource: wgpu::BindingResource::Buffer(waveform_buffer.as_entire_buffer_binding()),
-        },
-      ],
-      label: Some("fragment_bind_group"),
+    let channel_count = 1;
+    let channel_count_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Channel Count Buffer"),
+      contents: bytemuck::cast_slice(&[channel_count as u32]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
     });
 
+    let (spectrum_buffer, waveform_buffer, peaks_buffer) =
+      Self::create_buffers(device, 0, 0, channel_count);
+    let audio_data_bind_group = Self::create_bind_group(
+      device,
+      &audio_data_bind_group_layout,
+      &spectrum_buffer,
+      &waveform_buffer,
+      &channel_count_buffer,
+      &peaks_buffer,
+    );
+
     Self {
+      spectrum_len: 0,
+      waveform_len: 0,
+      channel_count,
       spectrum_buffer,
       waveform_buffer,
+      peaks_buffer,
+      channel_count_buffer,
       audio_data_bind_group,
       audio_data_bind_group_layout,
     }
   }
 
-  pub fn resize(&mut self, device: &wgpu::Device, size: usize) {
-    let (spectrum_buffer, waveform_buffer) = Self::create_buffers(device, size);
+  /// Recreates the buffers to hold `spectrum_len` spectrum and peak values and
+  /// `waveform_len` waveform samples for each of `channel_count` channels.
+  ///
+  /// The bind group references the buffers themselves, so it has to be rebuilt as well,
+  /// otherwise the GPU would keep reading from the old buffers.
+  pub fn resize(
+    &mut self,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    spectrum_len: usize,
+    waveform_len: usize,
+    channel_count: usize,
+  ) {
+    let channel_count = channel_count.max(1);
+    if spectrum_len == self.spectrum_len
+      && waveform_len == self.waveform_len
+      && channel_count == self.channel_count
+    {
+      return;
+    }
+
+    let (spectrum_buffer, waveform_buffer, peaks_buffer) =
+      Self::create_buffers(device, spectrum_len, waveform_len, channel_count);
+    self.audio_data_bind_group = Self::create_bind_group(
+      device,
+      &self.audio_data_bind_group_layout,
+      &spectrum_buffer,
+      &waveform_buffer,
+      &self.channel_count_buffer,
+      &peaks_buffer,
+    );
+    queue.write_buffer(
+      &self.channel_count_buffer,
+      0,
+      bytemuck::cast_slice(&[channel_count as u32]),
+    );
+
+    self.spectrum_len = spectrum_len;
+    self.waveform_len = waveform_len;
+    self.channel_count = channel_count;
     self.spectrum_buffer = spectrum_buffer;
     self.waveform_buffer = waveform_buffer;
+    self.peaks_buffer = peaks_buffer;
   }
 
   pub fn layout(&self) -> &wgpu::BindGroupLayout {
     &self.audio_data_bind_group_layout
   }
 
-  pub fn update_spectrum(&self, spectrum: &[f32], queue: &wgpu::Queue) {
-    queue.write_buffer(&self.spectrum_buffer, 0, bytemuck::cast_slice(&spectrum));
+  pub fn clear(&self, queue: &wgpu::Queue) {
+    let spectrum_zeros = vec![0.0f32; self.spectrum_len * self.channel_count];
+    let waveform_zeros = vec![0.0f32; self.waveform_len * self.channel_count];
+    queue.write_buffer(
+      &self.spectrum_buffer,
+      0,
+      bytemuck::cast_slice(&spectrum_zeros),
+    );
+    queue.write_buffer(
+      &self.waveform_buffer,
+      0,
+      bytemuck::cast_slice(&waveform_zeros),
+    );
+    queue.write_buffer(&self.peaks_buffer, 0, bytemuck::cast_slice(&spectrum_zeros));
   }
 
-  pub fn update_waveform(&self, waveform: &[f32], queue: &wgpu::Queue) {
-    queue.write_buffer(&self.waveform_buffer, 0, bytemuck::cast_slice(&waveform));
+  /// Uploads `frame`, which has to have the channels and lengths the buffers were sized for.
+  pub fn update(&self, frame: &AudioFrame, queue: &wgpu::Queue) {
+    self.update_channels(
+      &self.spectrum_buffer,
+      self.spectrum_len,
+      &frame.spectrum,
+      queue,
+    );
+    self.update_channels(
+      &self.waveform_buffer,
+      self.waveform_len,
+      &frame.waveform,
+      queue,
+    );
+    self.update_channels(&self.peaks_buffer, self.spectrum_len, &frame.peaks, queue);
+  }
+
+  fn update_channels(
+    &self,
+    buffer: &wgpu::Buffer,
+    len: usize,
+    channels: &[Vec<f32>],
+    queue: &wgpu::Queue,
+  ) {
+    // Channels that are too short are padded, so no stale values are left behind
+    let mut data = vec![0.0f32; len * self.channel_count];
+    for (target, channel) in data.chunks_exact_mut(len.max(1)).zip(channels) {
+      let len = channel.len().min(target.len());
+      target[..len].copy_from_slice(&channel[..len]);
+    }
+
+    queue.write_buffer(buffer, 0, bytemuck::cast_slice(&data));
   }
 }
 
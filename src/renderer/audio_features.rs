@@ -0,0 +1,450 @@
+use wgpu::util::DeviceExt;
+
+use crate::audio::{
+  AudioFrame, BandEnergies, Beat, Harmony, Loudness, LoudnessLevels, Mode, NamedBand,
+  PAN_BAND_COUNT, SpectralDescriptors, StereoImage, Tempo,
+};
+
+/// GPU layout of [`StereoImage`], matching the `Stereo` struct in the shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct StereoUniform {
+  mid: f32,
+  side: f32,
+  correlation: f32,
+  _padding: f32,
+  /// Packed into vec4s, since uniform arrays need a 16 byte stride
+  band_pan: [[f32; 4]; PAN_BAND_COUNT / 4],
+}
+
+impl From<&StereoImage> for StereoUniform {
+  fn from(stereo: &StereoImage) -> Self {
+    let mut band_pan = [[0.0; 4]; PAN_BAND_COUNT / 4];
+    for (packed, pan) in band_pan.iter_mut().flatten().zip(stereo.band_pan) {
+      *packed = pan;
+    }
+
+    Self {
+      mid: stereo.mid,
+      side: stereo.side,
+      correlation: stereo.correlation,
+      _padding: 0.0,
+      band_pan,
+    }
+  }
+}
+
+/// GPU layout of [`Beat`], matching the `Beat` struct in the shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct BeatUniform {
+  count: u32,
+  time_since: f32,
+  pulse: f32,
+  _padding: f32,
+}
+
+impl From<&Beat> for BeatUniform {
+  fn from(beat: &Beat) -> Self {
+    Self {
+      count: beat.count,
+      time_since: beat.time_since,
+      pulse: beat.pulse,
+      _padding: 0.0,
+    }
+  }
+}
+
+/// GPU layout of [`Tempo`], matching the `Tempo` struct in the shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct TempoUniform {
+  bpm: f32,
+  confidence: f32,
+  beat_phase: f32,
+  bar_phase: f32,
+  beats: f32,
+  _padding: [f32; 3],
+}
+
+impl From<&Tempo> for TempoUniform {
+  fn from(tempo: &Tempo) -> Self {
+    Self {
+      bpm: tempo.bpm,
+      confidence: tempo.confidence,
+      beat_phase: tempo.beat_phase,
+      bar_phase: tempo.bar_phase,
+      beats: tempo.beats,
+      _padding: [0.0; 3],
+    }
+  }
+}
+
+/// GPU layout of [`LoudnessLevels`], matching the `LoudnessLevels` struct in the shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct LoudnessLevelsUniform {
+  rms: f32,
+  true_peak: f32,
+  momentary: f32,
+  short_term: f32,
+  integrated: f32,
+  /// Structs in uniforms are 16 byte aligned
+  _padding: [f32; 3],
+}
+
+impl From<&LoudnessLevels> for LoudnessLevelsUniform {
+  fn from(levels: &LoudnessLevels) -> Self {
+    Self {
+      rms: levels.rms,
+      true_peak: levels.true_peak,
+      momentary: levels.momentary,
+      short_term: levels.short_term,
+      integrated: levels.integrated,
+      _padding: [0.0; 3],
+    }
+  }
+}
+
+/// GPU layout of [`Loudness`], matching the `Loudness` struct in the shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct LoudnessUniform {
+  program: LoudnessLevelsUniform,
+  /// The first and last channel, like the renderer shows them in split mode
+  left: LoudnessLevelsUniform,
+  right: LoudnessLevelsUniform,
+}
+
+impl Default for LoudnessUniform {
+  fn default() -> Self {
+    Self::from(&Loudness::default())
+  }
+}
+
+impl From<&Loudness> for LoudnessUniform {
+  fn from(loudness: &Loudness) -> Self {
+    let channel = |levels: Option<&LoudnessLevels>| {
+      LoudnessLevelsUniform::from(levels.unwrap_or(&loudness.program))
+    };
+
+    Self {
+      program: LoudnessLevelsUniform::from(&loudness.program),
+      left: channel(loudness.channels.first()),
+      right: channel(loudness.channels.last()),
+    }
+  }
+}
+
+/// GPU layout of [`Harmony`], matching the `Harmony` struct in the shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct HarmonyUniform {
+  /// Packed into vec4s, since uniform arrays need a 16 byte stride
+  chroma: [[f32; 4]; 3],
+  tonic: u32,
+  minor: u32,
+  key_confidence: f32,
+  _padding: f32,
+}
+
+impl From<&Harmony> for HarmonyUniform {
+  fn from(harmony: &Harmony) -> Self {
+    let mut chroma = [[0.0; 4]; 3];
+    for (packed, value) in chroma.iter_mut().flatten().zip(harmony.chroma) {
+      *packed = value;
+    }
+
+    Self {
+      chroma,
+      tonic: harmony.key.tonic as u32,
+      minor: (harmony.key.mode == Mode::Minor) as u32,
+      key_confidence: harmony.key.confidence,
+      _padding: 0.0,
+    }
+  }
+}
+
+/// GPU layout of [`SpectralDescriptors`], matching the `Descriptors` struct in the shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct DescriptorsUniform {
+  centroid: f32,
+  rolloff: f32,
+  flatness: f32,
+  flux: f32,
+  zero_crossing_rate: f32,
+  _padding: [f32; 3],
+}
+
+impl From<&SpectralDescriptors> for DescriptorsUniform {
+  fn from(descriptors: &SpectralDescriptors) -> Self {
+    Self {
+      centroid: descriptors.centroid,
+      rolloff: descriptors.rolloff,
+      flatness: descriptors.flatness,
+      flux: descriptors.flux,
+      zero_crossing_rate: descriptors.zero_crossing_rate,
+      _padding: [0.0; 3],
+    }
+  }
+}
+
+/// GPU layout of [`BandEnergies`], matching the `BandEnergies` struct in the shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct BandEnergiesUniform {
+  sub: f32,
+  bass: f32,
+  low_mid: f32,
+  mid: f32,
+  presence: f32,
+  air: f32,
+  /// Structs in uniforms are 16 byte aligned
+  _padding: [f32; 2],
+}
+
+impl From<&BandEnergies> for BandEnergiesUniform {
+  fn from(energies: &BandEnergies) -> Self {
+    Self {
+      sub: energies.get(NamedBand::Sub),
+      bass: energies.get(NamedBand::Bass),
+      low_mid: energies.get(NamedBand::LowMid),
+      mid: energies.get(NamedBand::Mid),
+      presence: energies.get(NamedBand::Presence),
+      air: energies.get(NamedBand::Air),
+      _padding: [0.0; 2],
+    }
+  }
+}
+
+/// GPU layout of the band energies of an [`AudioFrame`], matching the `Bands` struct in
+/// the shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct BandsUniform {
+  all: BandEnergiesUniform,
+  /// The first and last channel, like the renderer shows them in split mode
+  left: BandEnergiesUniform,
+  right: BandEnergiesUniform,
+}
+
+impl From<&AudioFrame> for BandsUniform {
+  fn from(frame: &AudioFrame) -> Self {
+    let channel = |energies: Option<&BandEnergies>| {
+      BandEnergiesUniform::from(energies.unwrap_or(&frame.band_energies))
+    };
+
+    Self {
+      all: BandEnergiesUniform::from(&frame.band_energies),
+      left: channel(frame.channel_band_energies.first()),
+      right: channel(frame.channel_band_energies.last()),
+    }
+  }
+}
+
+fn uniform_layout_entry(binding: u32) -> wgpu::BindGroupLayoutEntry {
+  wgpu::BindGroupLayoutEntry {
+    binding,
+    visibility: wgpu::ShaderStages::FRAGMENT,
+    ty: wgpu::BindingType::Buffer {
+      ty: wgpu::BufferBindingType::Uniform,
+      has_dynamic_offset: false,
+      min_binding_size: None,
+    },
+    count: None,
+  }
+}
+
+fn create_uniform_buffer(
+  device: &wgpu::Device,
+  label: &str,
+  contents: &impl bytemuck::Pod,
+) -> wgpu::Buffer {
+  device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+    label: Some(label),
+    contents: bytemuck::bytes_of(contents),
+    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+  })
+}
+
+/// Scalar analysis results, one uniform buffer per feature.
+pub struct AudioFeatures {
+  stereo_buffer: wgpu::Buffer,
+  beat_buffer: wgpu::Buffer,
+  tempo_buffer: wgpu::Buffer,
+  loudness_buffer: wgpu::Buffer,
+  harmony_buffer: wgpu::Buffer,
+  descriptors_buffer: wgpu::Buffer,
+  bands_buffer: wgpu::Buffer,
+  audio_features_bind_group: wgpu::BindGroup,
+  audio_features_bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl AudioFeatures {
+  pub async fn new(device: &wgpu::Device) -> Self {
+    let audio_features_bind_group_layout =
+      device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+          uniform_layout_entry(0),
+          uniform_layout_entry(1),
+          uniform_layout_entry(2),
+          uniform_layout_entry(3),
+          uniform_layout_entry(4),
+          uniform_layout_entry(5),
+          uniform_layout_entry(6),
+        ],
+        label: Some("audio_features_bind_group_layout"),
+      });
+
+    let stereo_buffer = create_uniform_buffer(device, "Stereo Buffer", &StereoUniform::default());
+    let beat_buffer = create_uniform_buffer(device, "Beat Buffer", &BeatUniform::default());
+    let tempo_buffer = create_uniform_buffer(device, "Tempo Buffer", &TempoUniform::default());
+    let loudness_buffer =
+      create_uniform_buffer(device, "Loudness Buffer", &LoudnessUniform::default());
+    let harmony_buffer =
+      create_uniform_buffer(device, "Harmony Buffer", &HarmonyUniform::default());
+    let descriptors_buffer =
+      create_uniform_buffer(device, "Descriptors Buffer", &DescriptorsUniform::default());
+    let bands_buffer = create_uniform_buffer(device, "Bands Buffer", &BandsUniform::default());
+
+    let audio_features_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+      layout: &audio_features_bind_group_layout,
+      entries: &[
+        wgpu::BindGroupEntry {
+          binding: 0,
+          resource: wgpu::BindingResource::Buffer(stereo_buffer.as_entire_buffer_binding()),
+        },
+        wgpu::BindGroupEntry {
+          binding: 1,
+          resource: wgpu::BindingResource::Buffer(beat_buffer.as_entire_buffer_binding()),
+        },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: wgpu::BindingResource::Buffer(tempo_buffer.as_entire_buffer_binding()),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: wgpu::BindingResource::Buffer(loudness_buffer.as_entire_buffer_binding()),
+        },
+        wgpu::BindGroupEntry {
+          binding: 4,
+          resource: wgpu::BindingResource::Buffer(harmony_buffer.as_entire_buffer_binding()),
+        },
+        wgpu::BindGroupEntry {
+          binding: 5,
+          resource: wgpu::BindingResource::Buffer(descriptors_buffer.as_entire_buffer_binding()),
+        },
+        wgpu::BindGroupEntry {
+          binding: 6,
+          resource: wgpu::BindingResource::Buffer(bands_buffer.as_entire_buffer_binding()),
+        },
+      ],
+      label: Some("audio_features_bind_group"),
+    });
+
+    Self {
+      stereo_buffer,
+      beat_buffer,
+      tempo_buffer,
+      loudness_buffer,
+      harmony_buffer,
+      descriptors_buffer,
+      bands_buffer,
+      audio_features_bind_group,
+      audio_features_bind_group_layout,
+    }
+  }
+
+  pub fn layout(&self) -> &wgpu::BindGroupLayout {
+    &self.audio_features_bind_group_layout
+  }
+
+  pub fn clear(&self, queue: &wgpu::Queue) {
+    queue.write_buffer(
+      &self.stereo_buffer,
+      0,
+      bytemuck::bytes_of(&StereoUniform::default()),
+    );
+    queue.write_buffer(
+      &self.beat_buffer,
+      0,
+      bytemuck::bytes_of(&BeatUniform::default()),
+    );
+    queue.write_buffer(
+      &self.tempo_buffer,
+      0,
+      bytemuck::bytes_of(&TempoUniform::default()),
+    );
+    queue.write_buffer(
+      &self.loudness_buffer,
+      0,
+      bytemuck::bytes_of(&LoudnessUniform::default()),
+    );
+    queue.write_buffer(
+      &self.harmony_buffer,
+      0,
+      bytemuck::bytes_of(&HarmonyUniform::default()),
+    );
+    queue.write_buffer(
+      &self.descriptors_buffer,
+      0,
+      bytemuck::bytes_of(&DescriptorsUniform::default()),
+    );
+    queue.write_buffer(
+      &self.bands_buffer,
+      0,
+      bytemuck::bytes_of(&BandsUniform::default()),
+    );
+  }
+
+  pub fn update(&self, frame: &AudioFrame, queue: &wgpu::Queue) {
+    queue.write_buffer(
+      &self.stereo_buffer,
+      0,
+      bytemuck::bytes_of(&StereoUniform::from(&frame.stereo)),
+    );
+    queue.write_buffer(
+      &self.beat_buffer,
+      0,
+      bytemuck::bytes_of(&BeatUniform::from(&frame.beat)),
+    );
+    queue.write_buffer(
+      &self.tempo_buffer,
+      0,
+      bytemuck::bytes_of(&TempoUniform::from(&frame.tempo)),
+    );
+    queue.write_buffer(
+      &self.loudness_buffer,
+      0,
+      bytemuck::bytes_of(&LoudnessUniform::from(&frame.loudness)),
+    );
+    queue.write_buffer(
+      &self.harmony_buffer,
+      0,
+      bytemuck::bytes_of(&HarmonyUniform::from(&frame.harmony)),
+    );
+    queue.write_buffer(
+      &self.descriptors_buffer,
+      0,
+      bytemuck::bytes_of(&DescriptorsUniform::from(&frame.descriptors)),
+    );
+    queue.write_buffer(
+      &self.bands_buffer,
+      0,
+      bytemuck::bytes_of(&BandsUniform::from(frame)),
+    );
+  }
+}
+
+pub trait BindAudioFeatures<'a> {
+  fn bind_audio_features(&mut self, index: u32, audio_features: &'a AudioFeatures);
+}
+impl<'a, 'b> BindAudioFeatures<'b> for wgpu::RenderPass<'a>
+where
+  'b: 'a,
+{
+  fn bind_audio_features(&mut self, index: u32, audio_features: &'b AudioFeatures) {
+    self.set_bind_group(index, &audio_features.audio_features_bind_group, &[]);
+  }
+}
@@ -5,6 +5,8 @@ use wgpu::util::DeviceExt;
 pub struct ExtraInfo {
   time_buffer: wgpu::Buffer,
   resolution_buffer: wgpu::Buffer,
+  show_meters_buffer: wgpu::Buffer,
+  activity_buffer: wgpu::Buffer,
   extra_info_bind_group: wgpu::BindGroup,
   extra_info_bind_group_layout: wgpu::BindGroupLayout,
 }
@@ -34,6 +36,26 @@ impl ExtraInfo {
             },
             count: None,
           },
+          wgpu::BindGroupLayoutEntry {
+            binding: 2,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: None,
+            },
+            count: None,
+          },
+          wgpu::BindGroupLayoutEntry {
+            binding: 3,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Buffer {
+              ty: wgpu::BufferBindingType::Uniform,
+              has_dynamic_offset: false,
+              min_binding_size: None,
+            },
+            count: None,
+          },
         ],
         label: Some("fragment_bind_group_layout"),
       });
@@ -50,6 +72,18 @@ impl ExtraInfo {
       usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
     });
 
+    let show_meters_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Show Meters Buffer"),
+      contents: bytemuck::cast_slice(&[0u32]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+
+    let activity_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+      label: Some("Activity Buffer"),
+      contents: bytemuck::cast_slice(&[1.0f32]),
+      usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+    });
+
     let extra_info_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
       layout: &extra_info_bind_group_layout,
       entries: &[
@@ -61,6 +95,14 @@ impl ExtraInfo {
           binding: 1,
           resource: wgpu::BindingResource::Buffer(resolution_buffer.as_entire_buffer_binding()),
         },
+        wgpu::BindGroupEntry {
+          binding: 2,
+          resource: wgpu::BindingResource::Buffer(show_meters_buffer.as_entire_buffer_binding()),
+        },
+        wgpu::BindGroupEntry {
+          binding: 3,
+          resource: wgpu::BindingResource::Buffer(activity_buffer.as_entire_buffer_binding()),
+        },
       ],
       label: Some("fragment_bind_group"),
     });
@@ -68,6 +110,8 @@ impl ExtraInfo {
     Self {
       time_buffer,
       resolution_buffer,
+      show_meters_buffer,
+      activity_buffer,
       extra_info_bind_group,
       extra_info_bind_group_layout,
     }
@@ -96,6 +140,18 @@ impl ExtraInfo {
       bytemuck::cast_slice(&[new_resolution.width, new_resolution.height]),
     );
   }
+
+  pub fn update_show_meters(&self, show_meters: bool, queue: &wgpu::Queue) {
+    queue.write_buffer(
+      &self.show_meters_buffer,
+      0,
+      bytemuck::cast_slice(&[show_meters as u32]),
+    );
+  }
+
+  pub fn update_activity(&self, activity: f32, queue: &wgpu::Queue) {
+    queue.write_buffer(&self.activity_buffer, 0, bytemuck::cast_slice(&[activity]));
+  }
 }
 
 pub trait BindExtraInfo<'a> {
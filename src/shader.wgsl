@@ -40,22 +40,373 @@ var<uniform> time: f32;
 @group(0) @binding(1)
 var<uniform> resolution: vec2f;
 
+// 1 when the level and loudness meters are drawn over the visualization
+@group(0) @binding(2)
+var<uniform> show_meters: u32;
+
+// 1 while audio plays, 0 once the input has been silent long enough to idle
+@group(0) @binding(3)
+var<uniform> activity: f32;
+
+@group(1) @binding(0)
+var<storage, read> spectrum: array<f32>;
+
+@group(1) @binding(1)
+var<storage, read> waveform: array<f32>;
+
+// Spectrum and waveform hold this many channels one after another,
+// 1 when the channels are mixed
+@group(1) @binding(2)
+var<uniform> channel_count: u32;
+
+// Recent maximum of every spectrum value, laid out like the spectrum
+@group(1) @binding(3)
+var<storage, read> peaks: array<f32>;
+
+struct Stereo {
+  // RMS levels of (left + right) / 2 and (left - right) / 2
+  mid: f32,
+  side: f32,
+  // -1 opposite phase, 0 unrelated or silent, 1 mono
+  correlation: f32,
+  // Pan from -1 left to 1 right of 16 bands from low to high frequencies
+  band_pan: array<vec4f, 4>,
+}
+
+@group(2) @binding(0)
+var<uniform> stereo: Stereo;
+
+struct Beat {
+  // Beats detected so far
+  count: u32,
+  // Seconds since the last beat
+  time_since: f32,
+  // 1 on a beat, decaying towards 0 afterwards
+  pulse: f32,
+}
+
+@group(2) @binding(1)
+var<uniform> beat: Beat;
+
+struct Tempo {
+  // 0 until a tempo was estimated
+  bpm: f32,
+  // How periodic the beats are, from 0 to 1
+  confidence: f32,
+  // Position within the current beat and 4/4 bar, 0 on the beat and on the downbeat
+  beat_phase: f32,
+  bar_phase: f32,
+  // Beats since the start including the phase, keeps increasing smoothly at 120 bpm
+  // while no tempo is known
+  beats: f32,
+}
+
+@group(2) @binding(2)
+var<uniform> tempo: Tempo;
+
+struct LoudnessLevels {
+  // Linear RMS and true peak level over the last 400 ms
+  rms: f32,
+  true_peak: f32,
+  // K-weighted loudness in LUFS over 400 ms, 3 s and since the start, -70 for silence
+  momentary: f32,
+  short_term: f32,
+  integrated: f32,
+}
+
+struct Loudness {
+  // All channels together
+  @align(16) program: LoudnessLevels,
+  // The first and last channel, both the same for mono
+  @align(16) left: LoudnessLevels,
+  @align(16) right: LoudnessLevels,
+}
+
+@group(2) @binding(3)
+var<uniform> loudness: Loudness;
+
+struct Harmony {
+  // Energy of the pitch classes from C to B, the strongest is 1
+  chroma: array<vec4f, 3>,
+  // Estimated key, tonic from 0 for C to 11 for B
+  tonic: u32,
+  // 1 for a minor key
+  minor: u32,
+  // From 0 to 1
+  key_confidence: f32,
+}
+
+@group(2) @binding(4)
+var<uniform> harmony: Harmony;
+
+struct Descriptors {
+  // Mean frequency of the spectrum in Hz, how bright the sound is
+  centroid: f32,
+  // Frequency in Hz below which 85% of the spectral energy lies
+  rolloff: f32,
+  // 0 for pure tones up to 1 for white noise
+  flatness: f32,
+  // How much the spectrum changed since the last frame
+  flux: f32,
+  // Share of consecutive samples with a different sign, from 0 to 1
+  zero_crossing_rate: f32,
+}
+
+@group(2) @binding(5)
+var<uniform> descriptors: Descriptors;
+
+// RMS spectrum magnitude of named frequency bands, smoothed like the spectrum. The default
+// ranges are 20-60, 60-250, 250-500, 500-2000, 2000-6000 and 6000-20000 Hz
+struct BandEnergies {
+  sub: f32,
+  bass: f32,
+  low_mid: f32,
+  mid: f32,
+  presence: f32,
+  air: f32,
+}
+
+struct Bands {
+  // All channels together
+  @align(16) all: BandEnergies,
+  // The first and last channel, both the same for mono
+  @align(16) left: BandEnergies,
+  @align(16) right: BandEnergies,
+}
+
+@group(2) @binding(6)
+var<uniform> bands: Bands;
+
+// Linearly interpolated read of one channel of a storage array at a relative position (0..1),
+// so the data can be sampled at any resolution independent of its length.
+fn sample_spectrum(channel: u32, position: f32) -> f32 {
+  let len = arrayLength(&spectrum) / channel_count;
+  if (len == 0) {
+    return 0.0;
+  }
+  let offset = min(channel, channel_count - 1) * len;
+  let x = clamp(position, 0, 1) * f32(len - 1);
+  let index = u32(x);
+  let next = min(index + 1, len - 1);
+  return mix(spectrum[offset + index], spectrum[offset + next], fract(x));
+}
+
+fn sample_peaks(channel: u32, position: f32) -> f32 {
+  let len = arrayLength(&peaks) / channel_count;
+  if (len == 0) {
+    return 0.0;
+  }
+  let offset = min(channel, channel_count - 1) * len;
+  let x = clamp(position, 0, 1) * f32(len - 1);
+  let index = u32(x);
+  let next = min(index + 1, len - 1);
+  return mix(peaks[offset + index], peaks[offset + next], fract(x));
+}
+
+fn sample_waveform(channel: u32, position: f32) -> f32 {
+  let len = arrayLength(&waveform) / channel_count;
+  if (len == 0) {
+    return 0.0;
+  }
+  let offset = min(channel, channel_count - 1) * len;
+  let x = clamp(position, 0, 1) * f32(len - 1);
+  let index = u32(x);
+  let next = min(index + 1, len - 1);
+  return mix(waveform[offset + index], waveform[offset + next], fract(x));
+}
+
+// Band energies of a channel as shown on screen
+fn channel_bands(split: bool, channel: u32) -> BandEnergies {
+  if (split) {
+    if (channel > 0) {
+      return bands.right;
+    }
+    return bands.left;
+  }
+  return bands.all;
+}
+
+// Pan of the band at a relative position (0..1) of the spectrum.
+fn sample_band_pan(position: f32) -> f32 {
+  let band = min(u32(clamp(position, 0, 1) * 16), 15);
+  return stereo.band_pan[band / 4][band % 4];
+}
+
+// Momentary loudness of a channel as shown on screen, from 0 for quiet to 1 for loud
+fn loudness_level(split: bool, channel: u32) -> f32 {
+  var momentary = loudness.program.momentary;
+  if (split) {
+    momentary = select(loudness.left.momentary, loudness.right.momentary, channel > 0);
+  }
+  return smoothstep(-50, -10, momentary);
+}
+
+fn to_decibels(level: f32) -> f32 {
+  return 20 * log(max(level, 0.00001)) / log(10.0);
+}
+
+// Position of a level in dB or LUFS on a meter from -60 to 0
+fn meter_position(decibels: f32) -> f32 {
+  return clamp((decibels + 60) / 60, 0, 1);
+}
+
+fn meter_color(decibels: f32) -> vec3f {
+  if (decibels > -6) {
+    return vec3f(1, 0.2, 0.1);
+  }
+  if (decibels > -18) {
+    return vec3f(1, 0.8, 0.1);
+  }
+  return vec3f(0.2, 0.9, 0.3);
+}
+
+// Draws one meter column with a bar filled to `fill` and marks at `mark` and `hold`,
+// y runs from 0 at the bottom to 1 at the top of the column. Marks below the meter are hidden
+fn draw_meter(col: vec3f, y: f32, pixel: f32, fill: f32, mark: f32, hold: f32) -> vec3f {
+  var color = col * 0.25;
+  let fill_position = meter_position(fill);
+  if (y <= fill_position) {
+    color = meter_color(mix(-60.0, 0.0, y));
+  }
+  if (mark > -60 && abs(y - meter_position(mark)) < pixel) {
+    color = vec3f(1, 1, 1);
+  }
+  if (hold > -60 && abs(y - meter_position(hold)) < pixel) {
+    color = vec3f(0.3, 0.6, 1);
+  }
+  return color;
+}
+
+// Level meters for the left and right channel, RMS filled with a true peak mark, followed by
+// a loudness meter, momentary filled with short-term and integrated marks
+fn draw_meters(col: vec3f, frag_coord: vec2f) -> vec3f {
+  const column_width: f32 = 10;
+  const gap: f32 = 4;
+  const margin: f32 = 16;
+
+  let height = resolution.y * 0.4;
+  let y = (resolution.y - margin - frag_coord.y) / height;
+  let x = frag_coord.x - (resolution.x - margin - 3 * column_width - 2 * gap);
+  if (y < 0 || y > 1 || x < 0) {
+    return col;
+  }
+
+  let column = u32(x / (column_width + gap));
+  if (column > 2 || x - f32(column) * (column_width + gap) > column_width) {
+    return col;
+  }
+
+  let pixel = 1 / height;
+  if (column == 2) {
+    let program = loudness.program;
+    return draw_meter(col, y, pixel, program.momentary, program.short_term, program.integrated);
+  }
+
+  var levels = loudness.left;
+  if (column == 1) {
+    levels = loudness.right;
+  }
+  return draw_meter(col, y, pixel, to_decibels(levels.rms), to_decibels(levels.true_peak), -70);
+}
+
+// Hue angle of a pitch class, neighbours on the circle of fifths get similar hues
+fn pitch_class_angle(pitch_class: u32) -> f32 {
+  return f32((pitch_class * 7) % 12) * 0.5235988;
+}
+
+// Hue for the current harmony, fixed per key and pulled towards the chord that is playing
+fn harmony_hue() -> f32 {
+  let key_angle = pitch_class_angle(harmony.tonic);
+
+  var chord = vec2f(0);
+  var total: f32 = 0;
+  for (var pitch_class: u32 = 0; pitch_class < 12; pitch_class++) {
+    let energy = harmony.chroma[pitch_class / 4][pitch_class % 4];
+    let angle = pitch_class_angle(pitch_class);
+    chord += energy * vec2f(cos(angle), sin(angle));
+    total += energy;
+  }
+
+  // Chroma concentrated around one spot of the circle pulls the hue further, noise doesn't
+  let strength = length(chord) / max(total, 0.0001);
+  let offset = atan2(chord.y, chord.x) - key_angle;
+  return key_angle + 0.5 * strength * atan2(sin(offset), cos(offset));
+}
+
 @fragment
 fn fs_main(@builtin(position) fragCoord: vec4f) -> @location(0) vec4f {
-  let uv = fragCoord.xy / resolution;
+  // With split channels the screen shows the first channel on the left and the last one
+  // mirrored on the right, so a centered mono sound renders symmetrically
+  let split = channel_count > 1;
+  let is_right = split && fragCoord.x >= resolution.x * 0.5;
+  let channel = select(0u, channel_count - 1, is_right);
+
+  var view_size = resolution;
+  var view_coord = fragCoord.xy;
+  if (split) {
+    view_size.x = resolution.x * 0.5;
+    view_coord.x = select(fragCoord.x, resolution.x - fragCoord.x, is_right);
+  }
+
+  let uv = view_coord / view_size;
+
+  let channel_bands = channel_bands(split, channel);
+  let bass = max(channel_bands.sub, channel_bands.bass);
+  let mids = (channel_bands.low_mid + channel_bands.mid) / 2;
+  let highs = (channel_bands.presence + channel_bands.air) / 2;
+  let energy = (bass + mids + highs) / 3;
+
+  // Fade between the time based animation and the audio driven one,
+  // so silent input falls back to the original drift, and idling fades it out completely
+  let awake = smoothstep(0, 1, activity);
+  let audio_mix = smoothstep(0.002, 0.02, energy) * awake;
+
+  let fallback_c = vec2f(- 0.5 * cos(time / 11), - 0.2 * sin(time / 7));
+  // Every beat kicks the constant in a new direction, a golden angle away from the last one,
+  // and the pulse lets it spring back
+  let kick_angle = f32(beat.count) * 2.39996;
+  let kick = vec2f(cos(kick_angle), sin(kick_angle)) * 0.06 * beat.pulse;
+  let audio_c = vec2f(- 0.75 + 0.3 * clamp(bass, 0, 1), 0.15 + 0.25 * clamp(mids - highs, - 1, 1)) + kick;
+  let c = mix(fallback_c, audio_c, audio_mix);
+
+  // Loudness follows what is heard better than the raw spectrum does
+  let level = loudness_level(split, channel);
+  let zoom = 1 + (0.5 * level + 0.15 * beat.pulse) * audio_mix;
+
+  // Panned bass pulls the set towards its side, split channels already show both sides
+  let pan_offset = vec2f(select(0.3 * sample_band_pan(0.05) * audio_mix, 0, split), 0);
 
   // Make julia apear centered on screen
-  let juliaUv = (fragCoord.xy / resolution.y - vec2f(0.5 * resolution.x / resolution.y, 0.5)) * 2;
-  let c = vec2f(- 0.5 * cos(time / 11), - 0.2 * sin(time / 7));
+  let juliaUv = (view_coord / view_size.y - vec2f(0.5 * view_size.x / view_size.y, 0.5)) * 2 / zoom - pan_offset;
 
   let julia = julia(juliaUv, c);
-  var col = 0.5 + 0.5 * cos(3 + julia * 0.15 + vec3f(0, 2, 4));
+  // Wide, decorrelated mixes spread the colors further apart
+  let width = stereo.side / max(stereo.mid + stereo.side, 0.0001);
+  // Bright sounds shift the colors too, from 0 at 200 Hz to 1 at 12.8 kHz
+  let brightness = clamp(log2(max(descriptors.centroid, 1) / 200) / 6, 0, 1);
+  let color_offset = (4 * energy + 0.5 * sample_waveform(channel, uv.x) + width * (1 - stereo.correlation) + 0.5 * brightness) * audio_mix;
+  var col = 0.5 + 0.5 * cos(3 + color_offset + julia * 0.15 + vec3f(0, 2, 4));
 
   if (julia < 0.5) {
     col = vec3f(1, 1, 1);
   }
 
-  col = hue_shift(col, time * 2.0);
+  // Columns whose spectrum just fell from a peak glow until the peak decays
+  let peak_drop = sample_peaks(channel, uv.x) - sample_spectrum(channel, uv.x);
+  col *= 1 + 0.2 * clamp(peak_drop, 0, 1) * audio_mix;
+  col *= mix(1, 0.7 + 0.4 * level, audio_mix);
+
+  // Idling dims the colors, which slowly breathe instead
+  col *= mix(0.55 + 0.1 * sin(time / 5), 1, awake);
+
+  // The harmony picks the hue, the tempo sways it once every two bars,
+  // while idle the hue drifts on its own
+  let sway = mix(0.5 * sin(time / 23), 0.25 * sin(tempo.beats * 0.7853982), awake);
+  col = hue_shift(col, harmony_hue() + sway);
+
+  if (show_meters != 0) {
+    col = draw_meters(col, fragCoord.xy);
+  }
 
   return vec4f(col, 1);
 }